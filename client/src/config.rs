@@ -0,0 +1,105 @@
+use clap::Parser;
+use logging::{LogFormat, LogLevel};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_CONFIG: &str = "client.toml"; // 未指定 --config 时，存在则读取
+
+// 命令行参数，优先于配置文件
+#[derive(Debug, Parser)]
+#[command(name = "client", about = "Card game terminal client")]
+pub struct Args {
+    /// Path to the TOML config file [default: client.toml if present]
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+    /// Server address
+    #[arg(long)]
+    pub address: Option<String>,
+    /// Server port
+    #[arg(short, long)]
+    pub port: Option<u16>,
+    /// Log level for diagnostics on stderr
+    #[arg(long, value_enum)]
+    pub log_level: Option<LogLevel>,
+    /// Log output format
+    #[arg(long, value_enum)]
+    pub log_format: Option<LogFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub address: String,
+    pub port: u16,
+    pub log_level: LogLevel, // 日志只用于排查问题，默认不打扰终端界面
+    pub log_format: LogFormat,
+    // 账号只从配置文件读取，免得密码出现在命令行里；不填则以游客身份游戏
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            address: "127.0.0.1".to_string(),
+            port: 9000,
+            log_level: LogLevel::Warn,
+            log_format: LogFormat::Text,
+            username: None,
+            password: None,
+        }
+    }
+}
+
+impl Config {
+    // 读取配置文件并叠加命令行参数
+    pub fn load(args: Args) -> Result<Config, String> {
+        let mut config = match &args.config {
+            Some(path) => Config::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG).exists() => {
+                Config::from_file(Path::new(DEFAULT_CONFIG))?
+            }
+            None => Config::default(),
+        };
+
+        if let Some(address) = args.address {
+            config.address = address;
+        }
+        if let Some(port) = args.port {
+            config.port = port;
+        }
+        if let Some(log_level) = args.log_level {
+            config.log_level = log_level;
+        }
+        if let Some(log_format) = args.log_format {
+            config.log_format = log_format;
+        }
+
+        // 地址可以是主机名，这里只排除明显的错误
+        let address = config.address.trim();
+        if address.is_empty() || address.contains(char::is_whitespace) {
+            return Err(format!("Invalid server address: {:?}", config.address));
+        }
+        if config.port == 0 {
+            return Err("Invalid server port: 0".to_string());
+        }
+        if config.username.is_some() != config.password.is_some() {
+            return Err("Invalid config: username and password must be set together".to_string());
+        }
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Config, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read config file {}: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("Invalid config file {}: {}", path.display(), e))
+    }
+
+    pub fn credentials(&self) -> Option<(String, String)> {
+        self.username.clone().zip(self.password.clone())
+    }
+
+    pub fn server_addr(&self) -> String {
+        format!("{}:{}", self.address.trim(), self.port)
+    }
+}
@@ -1,64 +1,680 @@
+mod config;
+
+use clap::Parser;
+use config::{Args, Config};
 use game_core::*;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::time::Duration;
 use tokio::{
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
     net::TcpStream,
+    sync::mpsc,
 };
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-#[serde(tag = "type")]
-enum NetMessage {
-    Command(Command),
-    Event(Event),
-}
+use tracing::{debug, info, warn};
 
 #[tokio::main]
 async fn main() {
-    let stream = TcpStream::connect("127.0.0.1:9000").await.unwrap();
-    println!("Connected to server");
-    let mut my_id: Option<usize> = None;
+    let config = match Config::load(Args::parse()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    };
+    logging::init(config.log_level, config.log_format);
+    let addr = config.server_addr();
 
-    let (r, mut w) = stream.into_split();
-    let mut reader = BufReader::new(r).lines();
+    let mut my_id: Option<usize> = None;
+    let mut token: Option<String> = None; // 会话令牌，断线后用于重连
+    let mut credentials = config.credentials(); // 登录过的账号，每次连上后重新登录
+    let mut pending_login: Option<(String, String)> = None; // 等待服务器确认的账号
 
-    while let Ok(Some(line)) = reader.next_line().await {
-        // Debug: Print the received JSON line
-        println!("Received JSON: {}", line);
+    // 终端输入单独一个任务，逐行经通道交给连接解析发送
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    tokio::spawn(read_input(tx));
 
-        // Attempt to parse the JSON
-        let msg: NetMessage = match serde_json::from_str(&line) {
-            Ok(parsed_msg) => parsed_msg,
-            Err(e) => {
-                println!("Failed to parse message: {}", e);
+    loop {
+        let stream = match TcpStream::connect(&addr).await {
+            Ok(stream) => stream,
+            Err(e) if token.is_some() => {
+                info!(server = %addr, error = %e, "reconnect failed");
+                println!("Reconnect failed: {}, retrying...", e);
+                tokio::time::sleep(Duration::from_secs(2)).await;
                 continue;
             }
+            Err(e) => {
+                println!("Failed to connect to {}: {}", addr, e);
+                return;
+            }
         };
+        info!(server = %addr, "connected");
+        println!("Connected to server");
+
+        let (r, mut w) = stream.into_split();
+        let mut reader = BufReader::new(r).lines();
+
+        // 有账号先登录；有令牌则重连，否则先看看大厅里有哪些房间
+        let mut hello = vec![];
+        if let Some((username, password)) = &credentials {
+            hello.push(NetMessage::Login {
+                username: username.clone(),
+                password: password.clone(),
+            });
+        }
+        hello.push(match &token {
+            Some(token) => NetMessage::Reconnect {
+                token: token.clone(),
+            },
+            None => NetMessage::ListRooms,
+        });
+        let text: String = hello
+            .iter()
+            .map(|msg| serde_json::to_string(msg).unwrap() + "\n")
+            .collect();
+        if let Err(err) = w.write_all(text.as_bytes()).await {
+            warn!(error = %err, "write failed");
+            continue;
+        }
 
-        // 处理服务器发出的event消息
-        match msg {
-            //
-            NetMessage::Event(Event::PlayerAssigned { player_id }) => {
-                my_id = Some(player_id);
-                println!("You are player {}", player_id);
+        loop {
+            let line = tokio::select! {
+                line = reader.next_line() => match line {
+                    Ok(Some(line)) => line,
+                    _ => break,
+                },
+                Some(input) = rx.recv() => {
+                    let Some(msg) = parse_input(&input, my_id) else {
+                        continue;
+                    };
+                    // 成功后记下，注册之后重连时也用登录
+                    if let NetMessage::Register { username, password }
+                    | NetMessage::Login { username, password } = &msg
+                    {
+                        pending_login = Some((username.clone(), password.clone()));
+                    }
+                    let text = serde_json::to_string(&msg).unwrap() + "\n";
+                    if let Err(err) = w.write_all(text.as_bytes()).await {
+                        warn!(error = %err, "write failed");
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            debug!(%line, "received");
+            let msg: NetMessage = match serde_json::from_str(&line) {
+                Ok(parsed_msg) => parsed_msg,
+                Err(e) => {
+                    warn!(%line, error = %e, "failed to parse message");
+                    continue;
+                }
+            };
+
+            // 心跳直接回应，不打印
+            if let NetMessage::Ping { nonce } = msg {
+                let text = serde_json::to_string(&NetMessage::Pong { nonce }).unwrap() + "\n";
+                if let Err(err) = w.write_all(text.as_bytes()).await {
+                    warn!(error = %err, "write failed");
+                    break;
+                }
+                continue;
             }
 
-            NetMessage::Event(Event::CardsDealt { player_id, cards }) => {
-                if Some(player_id) == my_id {
+            // 处理服务器发出的消息
+            match msg {
+                NetMessage::RoomList { rooms } => {
+                    println!("Rooms:");
+                    for room in rooms {
+                        println!(
+                            "  #{} {} [{}/{}] rounds={}{}{}{}{}{}",
+                            room.room_id,
+                            room.name,
+                            room.players + room.bots,
+                            room.seats,
+                            room.rules.rounds,
+                            if room.bots > 0 {
+                                format!(" {} bots", room.bots)
+                            } else {
+                                String::new()
+                            },
+                            room.rating
+                                .map(|rating| format!(" rating~{}", rating))
+                                .unwrap_or_default(),
+                            if room.locked { " (password)" } else { "" },
+                            if room.started { " (playing)" } else { "" },
+                            if room.spectators > 0 {
+                                format!(" {} watching", room.spectators)
+                            } else {
+                                String::new()
+                            }
+                        );
+                    }
+                }
+
+                NetMessage::RoomJoined {
+                    room_id,
+                    invite_code,
+                } => {
+                    println!("Joined room {}", room_id);
+                    if let Some(code) = invite_code {
+                        println!("Invite code: {}", code);
+                    }
+                }
+
+                NetMessage::RoomState {
+                    seated,
+                    bots,
+                    ready,
+                    host,
+                    names,
+                    ratings,
+                } => {
+                    println!("Seats:");
+                    for seat in seated {
+                        println!(
+                            "  {}{}{}{}{}{}",
+                            seat,
+                            names
+                                .get(&seat)
+                                .map(|name| match ratings.get(&seat) {
+                                    Some(rating) => format!(" {} ({})", name, rating),
+                                    None => format!(" {}", name),
+                                })
+                                .unwrap_or_default(),
+                            if Some(seat) == my_id { " (you)" } else { "" },
+                            if bots.contains(&seat) { " (bot)" } else { "" },
+                            if Some(seat) == host { " [host]" } else { "" },
+                            if ready.contains(&seat) { " ready" } else { "" }
+                        );
+                    }
+                }
+
+                NetMessage::LeaderboardList { players } => {
+                    if players.is_empty() {
+                        println!("No finished games yet");
+                    }
+                    for (i, stats) in players.iter().enumerate() {
+                        println!("  {:>2}. {}", i + 1, format_stats(stats));
+                    }
+                }
+
+                NetMessage::Stats { username, stats } => match stats {
+                    Some(stats) => println!("{}", format_stats(&stats)),
+                    None => println!("No finished games for {}", username),
+                },
+
+                NetMessage::LoggedIn { username } => {
+                    if pending_login.is_some() {
+                        credentials = pending_login.take();
+                    }
+                    println!("Logged in as {}", username);
+                }
+
+                NetMessage::Spectating { room_id } => {
+                    println!("Watching room {}", room_id);
+                }
+
+                NetMessage::Casting {
+                    room_id,
+                    delay_rounds,
+                } => {
+                    println!(
+                        "Casting room {} with a delay of {} round(s)",
+                        room_id, delay_rounds
+                    );
+                }
+
+                NetMessage::CastStep {
+                    round,
+                    command,
+                    events,
+                } => {
+                    if let Some(command) = command {
+                        println!("[round {}] {:?}", round, command);
+                    }
+                    for event in events {
+                        match event {
+                            Event::CardsDealt { player_id, cards } => {
+                                let cards: Vec<String> =
+                                    cards.iter().map(|card| card.to_string()).collect();
+                                println!(
+                                    "[round {}] Player {} holds {}",
+                                    round,
+                                    player_id,
+                                    cards.join(" ")
+                                );
+                            }
+                            event => println!("[round {}]   {:?}", round, event),
+                        }
+                    }
+                }
+
+                NetMessage::ChatMessage { player_id, text } => {
+                    if Some(player_id) == my_id {
+                        println!("[chat] You: {}", text);
+                    } else {
+                        println!("[chat] Player {}: {}", player_id, text);
+                    }
+                }
+
+                NetMessage::EmoteMessage { player_id, emote } => {
+                    if Some(player_id) == my_id {
+                        println!("[emote] You: {}", emote.name());
+                    } else {
+                        println!("[emote] Player {}: {}", player_id, emote.name());
+                    }
+                }
+
+                NetMessage::RoomLeft => {
+                    my_id = None;
+                    token = None;
+                    println!("Back in the lobby");
+                }
+
+                NetMessage::PlayerIdle { player_id, idle } => {
+                    if idle {
+                        println!("Player {} is not responding", player_id);
+                    } else {
+                        println!("Player {} is back", player_id);
+                    }
+                }
+
+                NetMessage::GamePaused => {
+                    println!("Game paused");
+                }
+
+                NetMessage::GameResumed => {
+                    println!("Game resumed");
+                }
+
+                NetMessage::RoomClosed { reason } => {
+                    my_id = None;
+                    token = None;
+                    println!("Room closed: {}", reason);
+                }
+
+                NetMessage::Notice { text } => {
+                    println!("[notice] {}", text);
+                }
+
+                NetMessage::ServerShutdown { grace_secs } => {
+                    println!("Server is shutting down in {}s", grace_secs);
+                }
+
+                NetMessage::Error { message } => {
+                    println!("Error: {}", message);
+                }
+
+                //
+                NetMessage::Event(Event::PlayerAssigned {
+                    player_id,
+                    token: t,
+                }) => {
+                    my_id = Some(player_id);
+                    token = Some(t);
+                    println!("You are player {}", player_id);
+                }
+
+                NetMessage::Event(Event::CardsDealt { player_id, cards }) => {
+                    if Some(player_id) == my_id {
+                        println!("Your cards:");
+                        for card in cards {
+                            println!("{}", card.to_string());
+                        }
+                    }
+                }
+
+                NetMessage::Event(Event::Snapshot {
+                    player_id,
+                    hand,
+                    scores,
+                    round,
+                    phase,
+                    current_player,
+                    ..
+                }) => {
+                    my_id = Some(player_id);
+                    println!(
+                        "Resumed as player {} (round {}, {:?})",
+                        player_id, round, phase
+                    );
+                    println!("Scores: {:?}", scores);
+                    println!("Current player: {}", current_player);
                     println!("Your cards:");
-                    for card in cards {
+                    for card in hand {
                         println!("{}", card.to_string());
                     }
                 }
+
+                NetMessage::Event(Event::PublicSnapshot {
+                    scores,
+                    round,
+                    phase,
+                    current_player,
+                    hand_sizes,
+                    played,
+                    ..
+                }) => {
+                    println!("Round {} ({:?})", round, phase);
+                    println!("Scores: {:?}", scores);
+                    println!("Cards left: {:?}", hand_sizes);
+                    println!("Played this round: {:?}", played);
+                    println!("Current player: {}", current_player);
+                }
+
+                NetMessage::Event(Event::TurnTimer {
+                    player_id,
+                    seconds_left,
+                }) => {
+                    if Some(player_id) == my_id {
+                        println!("Your turn: {}s left", seconds_left);
+                    } else {
+                        println!("Player {}: {}s left", player_id, seconds_left);
+                    }
+                }
+
+                NetMessage::Event(Event::VoteStarted {
+                    player_id,
+                    motion,
+                    needed,
+                }) => {
+                    println!(
+                        "Player {} calls a vote to {} ({} yes votes needed): vote {} yes|no",
+                        player_id,
+                        motion_name(motion),
+                        needed,
+                        motion_name(motion)
+                    );
+                }
+
+                NetMessage::Event(Event::VoteCast { player_id, yes }) => {
+                    println!(
+                        "Player {} votes {}",
+                        player_id,
+                        if yes { "yes" } else { "no" }
+                    );
+                }
+
+                NetMessage::Event(Event::VoteEnded { motion, passed }) => {
+                    println!(
+                        "Vote to {} {}",
+                        motion_name(motion),
+                        if passed { "passed" } else { "failed" }
+                    );
+                }
+
+                NetMessage::Event(e) => {
+                    println!("Event: {:?}", e);
+                }
+
+                other => {
+                    warn!(msg = ?other, "unexpected message");
+                }
             }
+        }
 
-            NetMessage::Event(e) => {
-                println!("Event: {:?}", e);
+        // 连接断开：没有令牌说明不在任何房间里，直接退出
+        if token.is_none() {
+            return;
+        }
+        info!(server = %addr, "disconnected");
+        println!("Disconnected, reconnecting...");
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+/* ================= 终端输入 ================= */
+async fn read_input(tx: mpsc::UnboundedSender<String>) {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        if tx.send(line).is_err() {
+            return;
+        }
+    }
+}
+
+// 把一行输入解析成要发给服务器的消息；游戏指令需要已入座
+fn parse_input(line: &str, my_id: Option<usize>) -> Option<NetMessage> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    let msg = match words.as_slice() {
+        ["rooms"] => NetMessage::ListRooms,
+        ["quick"] => NetMessage::QuickMatch,
+        ["top", rest @ ..] => NetMessage::Leaderboard {
+            limit: rest.first().and_then(|n| n.parse().ok()),
+        },
+        ["stats", username] => NetMessage::GetStats {
+            username: username.to_string(),
+        },
+        ["register", username, password] => NetMessage::Register {
+            username: username.to_string(),
+            password: password.to_string(),
+        },
+        ["login", username, password] => NetMessage::Login {
+            username: username.to_string(),
+            password: password.to_string(),
+        },
+        // 聊天内容保留原样的空白
+        ["say", ..] => NetMessage::Chat {
+            text: line
+                .trim_start()
+                .strip_prefix("say")
+                .unwrap_or("")
+                .to_string(),
+        },
+        ["emote", name] => match Emote::from_name(name) {
+            Some(emote) => NetMessage::Emote { emote },
+            None => {
+                print_help();
+                return None;
             }
+        },
+        ["create", name, seats, rest @ ..] => {
+            let Ok(seats) = seats.parse() else {
+                print_help();
+                return None;
+            };
 
-            NetMessage::Command(_) => {
-                println!("Received a Command message, which is unexpected on the client.");
+            // 可选参数：轮数、private、pw=<密码>、timer=<秒>；不指定规则则用服务器默认值
+            let mut rules: Option<GameRules> = None;
+            let mut private = false;
+            let mut password = None;
+            for word in rest {
+                if *word == "private" {
+                    private = true;
+                } else if let Some(pw) = word.strip_prefix("pw=") {
+                    password = Some(pw.to_string());
+                } else if let Some(secs) = word.strip_prefix("timer=").and_then(|t| t.parse().ok())
+                {
+                    // 所有阶段统一时限，0 表示不限时
+                    rules.get_or_insert_with(GameRules::default).timers = TurnTimers::uniform(secs);
+                } else if let Ok(rounds) = word.parse() {
+                    rules.get_or_insert_with(GameRules::default).rounds = rounds;
+                }
+            }
+
+            NetMessage::CreateRoom {
+                name: name.to_string(),
+                seats: Some(seats),
+                rules,
+                private,
+                password,
             }
         }
-    }
+        ["join", room_id, rest @ ..] => match room_id.parse() {
+            Ok(room_id) => NetMessage::JoinRoom {
+                room_id,
+                password: rest.first().map(|pw| pw.to_string()),
+            },
+            Err(_) => {
+                print_help();
+                return None;
+            }
+        },
+        ["code", code, rest @ ..] => NetMessage::JoinByCode {
+            code: code.to_string(),
+            password: rest.first().map(|pw| pw.to_string()),
+        },
+        ["watch", room_id, rest @ ..] => match room_id.parse() {
+            Ok(room_id) => NetMessage::Spectate {
+                room_id,
+                password: rest.first().map(|pw| pw.to_string()),
+            },
+            Err(_) => {
+                print_help();
+                return None;
+            }
+        },
+        ["watchcode", code, rest @ ..] => NetMessage::SpectateByCode {
+            code: code.to_string(),
+            password: rest.first().map(|pw| pw.to_string()),
+        },
+        ["cast", room_id, key, rest @ ..] => match room_id.parse() {
+            Ok(room_id) => NetMessage::Cast {
+                room_id,
+                key: key.to_string(),
+                delay_rounds: rest.first().and_then(|d| d.parse().ok()),
+            },
+            Err(_) => {
+                print_help();
+                return None;
+            }
+        },
+        ["leave"] => NetMessage::LeaveRoom,
+        ["ready"] => NetMessage::SetReady { ready: true },
+        ["unready"] => NetMessage::SetReady { ready: false },
+        ["seat", seat] => match seat.parse() {
+            Ok(seat) => NetMessage::SwapSeat { seat },
+            Err(_) => {
+                print_help();
+                return None;
+            }
+        },
+        ["start"] => NetMessage::StartGame,
+        ["bots"] => NetMessage::AddBots,
+        ["unbot", seat] => match seat.parse() {
+            Ok(seat) => NetMessage::RemoveBot { seat },
+            Err(_) => {
+                print_help();
+                return None;
+            }
+        },
+        ["predict", rank] => NetMessage::Command(Command::Predict {
+            player_id: my_id?,
+            rank: rank.parse().ok(), // 非数字（如 none）表示不预测
+        }),
+        ["play", index] => match index.parse() {
+            Ok(card_index) => NetMessage::Command(Command::PlayCard {
+                player_id: my_id?,
+                card_index,
+            }),
+            Err(_) => {
+                print_help();
+                return None;
+            }
+        },
+        ["posterior", ids @ ..] => NetMessage::Command(Command::PosteriorPredict {
+            player_id: my_id?,
+            rank_list: if ids.is_empty() || ids == ["none"] {
+                None
+            } else {
+                Some(ids.iter().filter_map(|id| id.parse().ok()).collect())
+            },
+        }),
+        ["vote", motion, rest @ ..] => {
+            let Some(motion) = parse_motion(motion) else {
+                print_help();
+                return None;
+            };
+            NetMessage::Command(Command::Vote {
+                player_id: my_id?,
+                motion,
+                yes: !matches!(rest.first(), Some(&"no")),
+            })
+        }
+        [] => return None,
+        _ => {
+            print_help();
+            return None;
+        }
+    };
+
+    Some(msg)
+}
+
+fn format_stats(stats: &PlayerStats) -> String {
+    let percent = |accuracy: Option<f64>| {
+        accuracy
+            .map(|a| format!("{:.0}%", a * 100.0))
+            .unwrap_or_else(|| "-".to_string())
+    };
+    format!(
+        "{} [{:.0}]: {} games, {} wins, avg score {:.1}, avg place {:.2}, prior {}, posterior {}",
+        stats.username,
+        stats.rating,
+        stats.games,
+        stats.wins,
+        stats.average_score,
+        stats.average_placement,
+        percent(stats.prior_accuracy),
+        percent(stats.posterior_accuracy)
+    )
+}
+
+const MOTIONS: [(&str, Motion); 4] = [
+    ("pause", Motion::Pause),
+    ("resume", Motion::Resume),
+    ("abort", Motion::Abort),
+    ("restart", Motion::Restart),
+];
+
+fn parse_motion(name: &str) -> Option<Motion> {
+    MOTIONS
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, motion)| *motion)
+}
+
+fn motion_name(motion: Motion) -> &'static str {
+    MOTIONS
+        .iter()
+        .find(|(_, m)| *m == motion)
+        .map(|(name, _)| *name)
+        .unwrap_or("?")
+}
+
+fn print_help() {
+    println!("Commands:");
+    println!("  register <username> <password> create an account and log in");
+    println!("  login <username> <password>    log in, results are kept under your account");
+    println!("  rooms                          list rooms");
+    println!("  top [n]                        show the leaderboard");
+    println!("  stats <username>               show a player's stats");
+    println!("  create <name> <seats> [rounds] [private] [pw=<password>] [timer=<secs>]");
+    println!("                                 create a room and sit down");
+    println!("  quick                          join a waiting room, or open one");
+    println!("  join <room_id> [password]      join a public room");
+    println!("  code <invite_code> [password]  join a private room");
+    println!("  watch <room_id> [password]     watch a public room as a spectator");
+    println!("  watchcode <code> [password]    watch a private room as a spectator");
+    println!("  cast <room_id> <key> [delay]   watch every hand, delayed by rounds (casters)");
+    println!("  say <text>                     chat with the room");
+    let emotes: Vec<&str> = Emote::ALL.iter().map(|emote| emote.name()).collect();
+    println!(
+        "  emote <name>                   send an emote: {}",
+        emotes.join(", ")
+    );
+    println!("  leave                          leave the room");
+    println!("  ready / unready                toggle ready in the waiting room");
+    println!("  seat <seat>                    move to a seat, swapping if taken");
+    println!(
+        "  bots / unbot <seat>            fill empty seats with bots / remove one (host only)"
+    );
+    println!("  start                          start the game (host only)");
+    println!("  predict <rank|none>            predict your own rank this round");
+    println!("  play <card_index>              play a card from your hand");
+    println!("  posterior <ids...|none>        predict the ranking (first player only)");
+    println!(
+        "  vote <motion> [no]             call or answer a table vote: pause, resume, abort, restart"
+    );
 }
@@ -0,0 +1,38 @@
+use crate::card::Card;
+use crate::command::Command;
+use crate::state::GameState;
+use crate::state::Phase;
+
+// 机器人策略：轮到 player_id 时给出一条指令，否则返回 None
+// 不做预测，出手里最小的牌，不做后验预测
+pub fn bot_command(state: &GameState, player_id: usize) -> Option<Command> {
+    if state.actor() != Some(player_id) {
+        return None;
+    }
+
+    match state.phase {
+        Phase::PriorPrediction => Some(Command::Predict {
+            player_id,
+            rank: None,
+        }),
+        Phase::Play => {
+            let player = state.players.iter().find(|p| p.id == player_id)?;
+            let card_index = player
+                .hand
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| Card::compare(a, b, &player.hand))
+                .map(|(i, _)| i)?;
+
+            Some(Command::PlayCard {
+                player_id,
+                card_index,
+            })
+        }
+        Phase::PosteriorPrediction => Some(Command::PosteriorPredict {
+            player_id,
+            rank_list: None,
+        }),
+        Phase::End => None,
+    }
+}
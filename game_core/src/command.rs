@@ -1,8 +1,9 @@
 //use crate::card::Card;
+use crate::state::Motion;
 use serde::Deserialize;
 use serde::Serialize;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 //玩家发起的事件
 pub enum Command {
     //预测
@@ -20,9 +21,22 @@ pub enum Command {
         player_id: usize,
         rank_list: Option<Vec<usize>>,
     },
-    // 重开投票
-    Restart {
+    // 桌上投票：第一张赞成票即发起，之后其他人表态
+    Vote {
         player_id: usize,
+        motion: Motion,
         yes: bool,
     },
 }
+
+impl Command {
+    // 发起指令的玩家
+    pub fn player_id(&self) -> usize {
+        match self {
+            Command::Predict { player_id, .. }
+            | Command::PlayCard { player_id, .. }
+            | Command::PosteriorPredict { player_id, .. }
+            | Command::Vote { player_id, .. } => *player_id,
+        }
+    }
+}
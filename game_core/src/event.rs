@@ -1,8 +1,9 @@
 use crate::card::Card;
+use crate::state::{Motion, Phase};
 use serde::Deserialize;
 use serde::Serialize;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 //系统宣布发生的事件
 pub enum Event {
     // “player_id 的预测被记录了
@@ -40,10 +41,56 @@ pub enum Event {
     // 身份确认
     PlayerAssigned {
         player_id: usize,
+        token: String, // 会话令牌，断线后凭此重连
     },
     // player_id 这一局的手牌
     CardsDealt {
         player_id: usize,
         cards: Vec<Card>,
     },
+    // 行动倒计时
+    TurnTimer {
+        player_id: usize,
+        seconds_left: u32,
+    },
+    // player_id 超时，由系统代为执行默认操作
+    TurnTimedOut {
+        player_id: usize,
+    },
+    // 重连成功后下发的完整状态快照
+    Snapshot {
+        player_id: usize,
+        hand: Vec<Card>,       // 自己的手牌
+        scores: Vec<i32>,      // 按玩家 ID 排列的分数
+        round: u8,             // 当前轮数
+        phase: Phase,          // 当前阶段
+        start_player: usize,   // 本轮起始玩家 ID
+        current_player: usize, // 当前行动玩家 ID
+    },
+    // player_id 发起投票，需要 needed 张赞成票才能通过
+    VoteStarted {
+        player_id: usize,
+        motion: Motion,
+        needed: usize,
+    },
+    // player_id 投了一票
+    VoteCast {
+        player_id: usize,
+        yes: bool,
+    },
+    // 投票结束：通过、被否决或超时
+    VoteEnded {
+        motion: Motion,
+        passed: bool,
+    },
+    // 观众进入时下发的公开状态，不含任何手牌
+    PublicSnapshot {
+        scores: Vec<i32>,
+        round: u8,
+        phase: Phase,
+        start_player: usize,
+        current_player: usize,
+        hand_sizes: Vec<usize>, // 按玩家 ID 排列的剩余手牌数
+        played: Vec<usize>,     // 本轮已出牌的玩家
+    },
 }
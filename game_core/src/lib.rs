@@ -1,11 +1,15 @@
+mod bot;
 mod card;
 mod command;
 mod event;
+mod net;
 mod rules;
 mod state;
 
+pub use bot::*;
 pub use card::*;
 pub use command::*;
 pub use event::*;
+pub use net::*;
 pub use rules::*;
 pub use state::*;
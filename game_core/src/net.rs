@@ -0,0 +1,274 @@
+use crate::command::Command;
+use crate::event::Event;
+use crate::state::GameRules;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+//服务器与客户端之间的网络消息（每行一条 JSON）
+pub enum NetMessage {
+    // 玩家指令
+    Command(Command),
+    // 系统事件
+    Event(Event),
+    // 握手：凭会话令牌重连，取回原座位
+    Reconnect {
+        token: String,
+    },
+    // 账号：注册新账号，成功后即为登录状态
+    Register {
+        username: String,
+        password: String,
+    },
+    // 账号：登录，之后的对局记在账号名下；不登录以游客身份游戏
+    Login {
+        username: String,
+        password: String,
+    },
+    // 心跳：收到 Ping 的一方回复同一 nonce 的 Pong
+    Ping {
+        nonce: u64,
+    },
+    Pong {
+        nonce: u64,
+    },
+    // 大厅：列出所有房间
+    ListRooms,
+    // 排行榜：前若干名，缺省时用服务器的默认值
+    Leaderboard {
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    // 查询某个账号的统计
+    GetStats {
+        username: String,
+    },
+    // 大厅：创建房间，创建者自动入座；座位数、规则缺省时用服务器的默认值
+    CreateRoom {
+        name: String,
+        #[serde(default)]
+        seats: Option<usize>,
+        #[serde(default)]
+        rules: Option<GameRules>,
+        #[serde(default)]
+        private: bool, // 私密房间不出现在列表中，只能凭邀请码加入
+        #[serde(default)]
+        password: Option<String>, // 可选的入座密码
+    },
+    // 大厅：快速加入一个还在等人的公开房间，没有就新建一个
+    QuickMatch,
+    // 大厅：加入公开房间
+    JoinRoom {
+        room_id: u32,
+        #[serde(default)]
+        password: Option<String>,
+    },
+    // 大厅：凭邀请码加入私密房间
+    JoinByCode {
+        code: String,
+        #[serde(default)]
+        password: Option<String>,
+    },
+    // 大厅：以观众身份进入房间，只收公开信息
+    Spectate {
+        room_id: u32,
+        #[serde(default)]
+        password: Option<String>,
+    },
+    // 大厅：凭邀请码观看私密房间
+    SpectateByCode {
+        code: String,
+        #[serde(default)]
+        password: Option<String>,
+    },
+    // 大厅：以解说身份观看，能看到所有手牌，但延后若干轮
+    Cast {
+        room_id: u32,
+        key: String,
+        #[serde(default)]
+        delay_rounds: Option<u8>, // 不得少于服务器设置的最小延迟
+    },
+    // 房间内：聊天，长度和频率受限
+    Chat {
+        text: String,
+    },
+    // 房间内：预设的表情
+    Emote {
+        emote: Emote,
+    },
+    // 离开当前房间，回到大厅
+    LeaveRoom,
+    // 等人阶段：设置准备状态
+    SetReady {
+        ready: bool,
+    },
+    // 等人阶段：换到指定座位，座位上有人则与其互换
+    SwapSeat {
+        seat: usize,
+    },
+    // 等人阶段：房主用机器人补满空座位
+    AddBots,
+    // 等人阶段：房主移除某个座位上的机器人
+    RemoveBot {
+        seat: usize,
+    },
+    // 等人阶段：房主开局（需坐满且全部准备）
+    StartGame,
+    // 服务器下发：房间列表
+    RoomList {
+        rooms: Vec<RoomInfo>,
+    },
+    // 服务器下发：排行榜
+    LeaderboardList {
+        players: Vec<PlayerStats>,
+    },
+    // 服务器下发：某个账号的统计，没有已结束的对局时为 None
+    Stats {
+        username: String,
+        stats: Option<PlayerStats>,
+    },
+    // 服务器下发：已进入房间
+    RoomJoined {
+        room_id: u32,
+        invite_code: Option<String>, // 私密房间的邀请码，便于分享
+    },
+    // 服务器下发：注册或登录成功
+    LoggedIn {
+        username: String,
+    },
+    // 服务器下发：已作为观众进入房间
+    Spectating {
+        room_id: u32,
+    },
+    // 服务器下发：已作为解说进入房间
+    Casting {
+        room_id: u32,
+        delay_rounds: u8,
+    },
+    // 服务器下发给解说：延迟放出的一步，包括指令本身和它产生的全部事件
+    CastStep {
+        round: u8,
+        command: Option<Command>, // 发牌等系统事件没有指令
+        events: Vec<Event>,
+    },
+    // 服务器下发：已回到大厅
+    RoomLeft,
+    // 服务器下发：等人阶段的座位情况
+    RoomState {
+        seated: Vec<usize>,  // 已入座的座位（含机器人）
+        bots: Vec<usize>,    // 机器人座位
+        ready: Vec<usize>,   // 已准备的座位
+        host: Option<usize>, // 房主座位
+        #[serde(default)]
+        names: BTreeMap<usize, String>, // 登录玩家的用户名，游客和机器人没有
+        #[serde(default)]
+        ratings: BTreeMap<usize, i32>, // 登录玩家的分数
+    },
+    // 服务器下发：某个座位的连接错过心跳 / 恢复
+    PlayerIdle {
+        player_id: usize,
+        idle: bool,
+    },
+    // 服务器下发：对局暂停 / 继续
+    GamePaused,
+    GameResumed,
+    // 服务器下发：房间已关闭，连接随后断开
+    RoomClosed {
+        reason: String,
+    },
+    // 服务器下发：即将停机，宽限期过后断开连接，重启后可凭会话令牌重连
+    ServerShutdown {
+        grace_secs: u64,
+    },
+    // 服务器下发：运维公告
+    Notice {
+        text: String,
+    },
+    // 服务器下发：房间内某位玩家的聊天 / 表情
+    ChatMessage {
+        player_id: usize,
+        text: String,
+    },
+    EmoteMessage {
+        player_id: usize,
+        emote: Emote,
+    },
+    // 服务器下发：请求被拒绝
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+// 大厅中展示的房间概要
+pub struct RoomInfo {
+    pub room_id: u32,
+    pub name: String,
+    pub seats: usize,   // 座位数
+    pub players: usize, // 已入座人数（不含机器人）
+    #[serde(default)]
+    pub bots: usize, // 机器人座位数
+    pub rules: GameRules,
+    pub started: bool, // 是否已开局
+    pub locked: bool,  // 是否需要密码
+    #[serde(default)]
+    pub spectators: usize, // 观众人数
+    #[serde(default)]
+    pub rating: Option<i32>, // 已入座的登录玩家的平均分，没有登录玩家时为 None
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+// 账号的累计统计，只算正常结束的对局
+pub struct PlayerStats {
+    pub username: String,
+    pub games: u32,
+    pub wins: u32, // 最终得分第一（含并列）
+    pub average_score: f64,
+    pub prior_accuracy: Option<f64>, // 先验预测猜中的比例，没预测过为 None
+    pub posterior_accuracy: Option<f64>, // 后验预测猜中的名次比例
+    pub average_placement: f64,
+    pub rating: f64,      // 多人 Elo 分数
+    pub rated_games: u32, // 计入分数的局数
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+// 预设表情，客户端自行决定如何显示
+pub enum Emote {
+    Hello,
+    Thanks,
+    Oops,
+    Wow,
+    Thinking,
+    GoodGame,
+}
+
+impl Emote {
+    pub const ALL: [Emote; 6] = [
+        Emote::Hello,
+        Emote::Thanks,
+        Emote::Oops,
+        Emote::Wow,
+        Emote::Thinking,
+        Emote::GoodGame,
+    ];
+
+    // 与序列化时的名字一致
+    pub fn name(&self) -> &'static str {
+        match self {
+            Emote::Hello => "hello",
+            Emote::Thanks => "thanks",
+            Emote::Oops => "oops",
+            Emote::Wow => "wow",
+            Emote::Thinking => "thinking",
+            Emote::GoodGame => "goodgame",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Emote> {
+        Emote::ALL
+            .into_iter()
+            .find(|emote| emote.name().eq_ignore_ascii_case(name))
+    }
+}
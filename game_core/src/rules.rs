@@ -1,3 +1,4 @@
+use crate::GameRules;
 use crate::GameState;
 use crate::PlayerState;
 use crate::Rank;
@@ -5,12 +6,16 @@ use crate::Suit;
 use crate::card::Card;
 use crate::command::Command;
 use crate::event::Event;
-use crate::state::Phase;
+use crate::state::{Motion, Phase, TableVote};
 
 use std::cmp::Ordering;
 
 impl GameState {
     pub fn new(players: Vec<PlayerState>) -> Self {
+        Self::with_rules(players, GameRules::default())
+    }
+
+    pub fn with_rules(players: Vec<PlayerState>, rules: GameRules) -> Self {
         let cp = players[0].id;
         GameState {
             players,
@@ -20,6 +25,9 @@ impl GameState {
             current_player: cp,
             table: vec![],
             is_card: false,
+            rules,
+            vote: None,
+            votes_held: 0,
         }
     }
 
@@ -51,6 +59,21 @@ impl GameState {
         Ok(())
     }
 
+    // 当前应当行动的玩家，游戏结束时为 None
+    pub fn actor(&self) -> Option<usize> {
+        match self.phase {
+            Phase::PriorPrediction | Phase::Play => Some(self.current_player),
+            Phase::PosteriorPrediction => Some(self.start_player),
+            Phase::End => None,
+        }
+    }
+
+    // 轮到下一位玩家；转完一圈回到首位玩家时返回 true
+    fn next_player(&mut self) -> bool {
+        self.current_player = (self.current_player + 1) % self.players.len();
+        self.current_player == self.start_player
+    }
+
     pub fn apply(&mut self, cmd: Command) -> Result<Vec<Event>, String> {
         match cmd {
             // 先验预测
@@ -70,7 +93,14 @@ impl GameState {
                 player.has_predicted = true;
 
                 // 生成事件
-                Ok(vec![Event::PredictionAccepted { player_id }])
+                let mut events = vec![Event::PredictionAccepted { player_id }];
+
+                // 所有人预测完毕，进入出牌阶段
+                if self.next_player() {
+                    self.phase = Phase::Play;
+                    events.push(Event::PhaseChanged);
+                }
+                Ok(events)
             }
             // 出牌
             Command::PlayCard {
@@ -92,10 +122,18 @@ impl GameState {
 
                 //出牌
                 let card = player.hand.remove(card_index);
-                player.has_predicted = true;
+                player.has_played = true;
+                self.table.push((player_id, card));
 
                 // 生成事件
-                Ok(vec![Event::CardPlayed { player_id }])
+                let mut events = vec![Event::CardPlayed { player_id }];
+
+                // 所有人出牌完毕，由首位玩家进行后验预测
+                if self.next_player() {
+                    self.phase = Phase::PosteriorPrediction;
+                    events.push(Event::PhaseChanged);
+                }
+                Ok(events)
             }
 
             // 后验预测
@@ -118,13 +156,79 @@ impl GameState {
                 player.posterior_prediction = rank_list;
                 player.has_predicted = true;
 
-                // 生成事件
-                Ok(vec![Event::PosteriorPredictionAccepted { player_id }])
+                // 生成事件并结算本轮
+                let mut events = vec![Event::PosteriorPredictionAccepted { player_id }];
+                events.extend(self.finish_round());
+                events.push(if self.phase == Phase::End {
+                    Event::GameEnded
+                } else {
+                    Event::PhaseChanged
+                });
+                Ok(events)
             }
 
-            // 下一局投票
-            Command::Restart { player_id, yes } => Ok(vec![]),
+            // 桌上投票
+            Command::Vote {
+                player_id,
+                motion,
+                yes,
+            } => self.vote(player_id, motion, yes),
+        }
+    }
+
+    // 没有进行中的投票时，赞成票发起新投票；同一时间只能有一个投票
+    fn vote(&mut self, player_id: usize, motion: Motion, yes: bool) -> Result<Vec<Event>, String> {
+        // 重开只能在对局结束后发起，其余议题只能在对局中
+        match (self.phase == Phase::End, motion == Motion::Restart) {
+            (true, false) => return Err("Game already ended".to_string()),
+            (false, true) => return Err("Game not finished".to_string()),
+            _ => {}
+        }
+        if player_id >= self.players.len() {
+            return Err("Invalid player".to_string());
+        }
+
+        let mut events = vec![];
+        let vote = match &mut self.vote {
+            None if !yes => return Err("No vote in progress".to_string()),
+            None => {
+                self.votes_held += 1;
+                events.push(Event::VoteStarted {
+                    player_id,
+                    motion,
+                    needed: motion.needed(self.players.len()),
+                });
+                self.vote.insert(TableVote {
+                    id: self.votes_held,
+                    motion,
+                    ballots: vec![None; self.players.len()],
+                })
+            }
+            Some(vote) if vote.motion != motion => {
+                return Err(format!("A vote to {:?} is in progress", vote.motion));
+            }
+            Some(vote) if vote.ballots[player_id].is_some() => {
+                return Err("Already voted".to_string());
+            }
+            Some(vote) => vote,
+        };
+
+        vote.ballots[player_id] = Some(yes);
+        events.push(Event::VoteCast { player_id, yes });
+        if let Some(passed) = vote.outcome() {
+            self.vote = None;
+            events.push(Event::VoteEnded { motion, passed });
         }
+        Ok(events)
+    }
+
+    // 投票超时：仍是同一次投票时作废
+    pub fn expire_vote(&mut self, id: u32) -> Option<Event> {
+        let vote = self.vote.take_if(|vote| vote.id == id)?;
+        Some(Event::VoteEnded {
+            motion: vote.motion,
+            passed: false,
+        })
     }
 
     fn finish_round(&mut self) -> Vec<Event> {
@@ -137,28 +241,13 @@ impl GameState {
         table.sort_by(|a, b| Card::compare(&a.1, &b.1, &cards));
 
         // 初始化分数和排名
-        let scores = [2, 1, 0, -1, -2]; // 不同排名对应的分数变化
+        let scores = rank_scores(self.players.len()); // 不同排名对应的分数变化
         let mut delta = vec![0; self.players.len()]; // 每个玩家的分数变化
         let mut ranking = vec![]; // 本轮排名
 
         let mut prediction: Vec<usize> = vec![];
         let mut posterior_prediction: Vec<usize> = vec![];
 
-        // 统计后验预测结果
-        let mut accurate_count = 0; // 准确预测个数
-        if let Some(p) = self.players[first_player].posterior_prediction.clone() {
-            posterior_prediction = p;
-
-            // 统计排名预测的准确个数
-            for (predicted_rank, &player_id) in posterior_prediction.iter().enumerate() {
-                if ranking.get(predicted_rank) == Some(&player_id) {
-                    accurate_count += 1;
-                }
-            }
-            //计算分数变化
-            delta[first_player] += scores[5 - accurate_count];
-        }
-
         // 遍历牌桌，按排名计算分数和排名
         for (player_rank, (player_id, _)) in table.iter().rev().enumerate() {
             // 根据排名调整分数
@@ -177,19 +266,46 @@ impl GameState {
             }
         }
 
+        // 统计后验预测结果
+        let mut accurate_count = 0; // 准确预测个数
+        if let Some(p) = self.players[first_player].posterior_prediction.clone() {
+            posterior_prediction = p;
+
+            // 统计排名预测的准确个数
+            for (predicted_rank, &player_id) in posterior_prediction.iter().enumerate() {
+                if ranking.get(predicted_rank) == Some(&player_id) {
+                    accurate_count += 1;
+                }
+            }
+            // 计算分数变化：全部猜中拿第一名的分，每少猜中一个递减一档，最低为最后一名的分
+            let index = (scores.len() - accurate_count).min(scores.len() - 1);
+            delta[first_player] += scores[index];
+        }
+
         for i in 0..self.players.len() {
             self.players[i].score += delta[i];
             prediction.push(self.players[i].prediction.unwrap_or(0));
             self.players[i].prediction = None;
+            self.players[i].posterior_prediction = None;
+            self.players[i].has_predicted = false;
+            self.players[i].has_played = false;
         }
 
         self.table.clear();
         self.round += 1;
-        self.phase = if self.round >= 5 {
+
+        // 下一轮由下一位玩家先手
+        self.start_player = (self.start_player + 1) % self.players.len();
+        self.current_player = self.start_player;
+        self.phase = if self.round >= self.rules.rounds {
             Phase::End
         } else {
             Phase::PriorPrediction
         };
+        // 对局结束，未决的投票随之作废
+        if self.phase == Phase::End {
+            self.vote = None;
+        }
 
         vec![Event::RoundResult {
             cards,
@@ -200,3 +316,271 @@ impl GameState {
         }]
     }
 }
+
+// 各名次的分数变化：前一半依次 +2、+1，后一半对称为负，人数为奇数时中间为 0，总和为 0
+fn rank_scores(players: usize) -> Vec<i32> {
+    let half = players / 2;
+    (0..players)
+        .map(|rank| {
+            let top = rank.min(players - 1 - rank);
+            let score = (2 - top as i32).max(1);
+            if players % 2 == 1 && rank == half {
+                0
+            } else if rank < half {
+                score
+            } else {
+                -score
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 每人一张黑桃，牌面依次为 2、3、4……，座位号越大牌越大
+    fn game(seats: usize, rounds: u8) -> GameState {
+        let ranks = [Rank::Two, Rank::Three, Rank::Four, Rank::Five, Rank::Six];
+        let players = (0..seats)
+            .map(|id| PlayerState {
+                id,
+                is_first: false,
+                hand: vec![Card {
+                    rank: ranks[id],
+                    suit: Suit::Spade,
+                }],
+                score: 0,
+                prediction: None,
+                has_predicted: false,
+                has_played: false,
+                posterior_prediction: None,
+            })
+            .collect();
+        GameState::with_rules(
+            players,
+            GameRules {
+                rounds,
+                ..GameRules::default()
+            },
+        )
+    }
+
+    // 从首位玩家开始按顺序预测、出牌，再由首位玩家做后验预测，返回本轮分数变化
+    fn play_round(
+        game: &mut GameState,
+        predictions: &[Option<usize>],
+        posterior: Option<Vec<usize>>,
+    ) -> Vec<i32> {
+        let n = game.players.len();
+        for i in 0..n {
+            let player_id = (game.start_player + i) % n;
+            game.apply(Command::Predict {
+                player_id,
+                rank: predictions[player_id],
+            })
+            .unwrap();
+        }
+        for i in 0..n {
+            let player_id = (game.start_player + i) % n;
+            game.apply(Command::PlayCard {
+                player_id,
+                card_index: 0,
+            })
+            .unwrap();
+        }
+        let events = game
+            .apply(Command::PosteriorPredict {
+                player_id: game.start_player,
+                rank_list: posterior,
+            })
+            .unwrap();
+        events
+            .into_iter()
+            .find_map(|event| match event {
+                Event::RoundResult { score_delta, .. } => Some(score_delta),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn phases_follow_turn_order() {
+        let mut game = game(3, 2);
+        assert_eq!(game.actor(), Some(0));
+        assert!(
+            game.apply(Command::Predict {
+                player_id: 1,
+                rank: None
+            })
+            .is_err()
+        );
+
+        for player_id in 0..3 {
+            assert_eq!(game.phase, Phase::PriorPrediction);
+            game.apply(Command::Predict {
+                player_id,
+                rank: None,
+            })
+            .unwrap();
+        }
+        assert_eq!(game.phase, Phase::Play);
+        assert_eq!(game.actor(), Some(0));
+
+        for player_id in 0..3 {
+            game.apply(Command::PlayCard {
+                player_id,
+                card_index: 0,
+            })
+            .unwrap();
+        }
+        assert_eq!(game.phase, Phase::PosteriorPrediction);
+        assert_eq!(game.actor(), Some(0));
+        assert!(
+            game.apply(Command::PosteriorPredict {
+                player_id: 1,
+                rank_list: None
+            })
+            .is_err()
+        );
+
+        let events = game
+            .apply(Command::PosteriorPredict {
+                player_id: 0,
+                rank_list: None,
+            })
+            .unwrap();
+        assert!(matches!(events.last(), Some(Event::PhaseChanged)));
+        assert_eq!(game.round, 1);
+        assert_eq!(game.phase, Phase::PriorPrediction);
+        // 下一轮由下一位玩家先手
+        assert_eq!(game.start_player, 1);
+        assert_eq!(game.actor(), Some(1));
+        assert!(
+            game.players
+                .iter()
+                .all(|p| !p.has_played && !p.has_predicted)
+        );
+    }
+
+    #[test]
+    fn last_round_ends_the_game() {
+        let mut game = game(2, 1);
+        play_round(&mut game, &[None, None], None);
+        assert_eq!(game.phase, Phase::End);
+        assert_eq!(game.actor(), None);
+    }
+
+    #[test]
+    fn round_scoring() {
+        let mut game = game(5, 1);
+        // 排名为 4 3 2 1 0；4 号猜中第一 +2，0 号猜错 -2，0 号后验全部猜中 +2
+        let delta = play_round(
+            &mut game,
+            &[Some(1), None, None, None, Some(1)],
+            Some(vec![4, 3, 2, 1, 0]),
+        );
+        assert_eq!(delta, vec![-2, -1, 0, 1, 4]);
+        let scores: Vec<i32> = game.players.iter().map(|p| p.score).collect();
+        assert_eq!(scores, delta);
+    }
+
+    #[test]
+    fn restart_vote_only_after_the_game() {
+        let vote = |motion| Command::Vote {
+            player_id: 0,
+            motion,
+            yes: true,
+        };
+        let mut game = game(2, 1);
+        assert!(game.apply(vote(Motion::Restart)).is_err());
+
+        play_round(&mut game, &[None, None], None);
+        assert!(game.apply(vote(Motion::Pause)).is_err());
+        let events = game.apply(vote(Motion::Restart)).unwrap();
+        assert!(matches!(events.first(), Some(Event::VoteStarted { .. })));
+    }
+
+    #[test]
+    fn rank_scores_sum_to_zero() {
+        assert_eq!(rank_scores(2), vec![2, -2]);
+        assert_eq!(rank_scores(3), vec![2, 0, -2]);
+        assert_eq!(rank_scores(4), vec![2, 1, -1, -2]);
+        assert_eq!(rank_scores(5), vec![2, 1, 0, -1, -2]);
+        for players in 1..=8 {
+            assert_eq!(rank_scores(players).iter().sum::<i32>(), 0);
+        }
+    }
+
+    #[test]
+    fn round_scoring_with_fewer_seats() {
+        let mut three = game(3, 1);
+        // 排名为 2 1 0；0 号后验中 1 个，按三人桌表最低 -2
+        let delta = play_round(&mut three, &[None; 3], Some(vec![0, 1, 2]));
+        assert_eq!(delta, vec![-4, 0, 2]);
+
+        let mut four = game(4, 1);
+        // 排名为 3 2 1 0；0 号后验中 2 个，得 scores[2] = -1
+        let delta = play_round(&mut four, &[None; 4], Some(vec![3, 2, 0, 1]));
+        assert_eq!(delta, vec![-3, -1, 1, 2]);
+    }
+
+    #[test]
+    fn posterior_bonus_by_accuracy() {
+        let cases = [
+            (vec![4, 3, 2, 1, 0], 2),  // 全中
+            (vec![4, 3, 2, 0, 1], 0),  // 中 3 个
+            (vec![4, 3, 0, 2, 1], -1), // 中 2 个
+            (vec![0, 1, 2, 3, 4], -2), // 中 1 个
+            (vec![0, 1, 3, 2, 4], -2), // 一个不中，最低 -2
+        ];
+        for (posterior, bonus) in cases {
+            let mut game = game(5, 1);
+            let delta = play_round(&mut game, &[None; 5], Some(posterior.clone()));
+            assert_eq!(delta[0], -2 + bonus, "posterior {:?}", posterior);
+        }
+    }
+
+    #[test]
+    fn posterior_scored_against_this_rounds_ranking() {
+        // 后验预测须在本轮排名算出之后再比对，否则排名为空、永远一个不中
+        let mut game = game(5, 1);
+        for player_id in 0..5 {
+            game.apply(Command::Predict {
+                player_id,
+                rank: None,
+            })
+            .unwrap();
+        }
+        for player_id in 0..5 {
+            game.apply(Command::PlayCard {
+                player_id,
+                card_index: 0,
+            })
+            .unwrap();
+        }
+        let events = game
+            .apply(Command::PosteriorPredict {
+                player_id: 0,
+                rank_list: Some(vec![4, 3, 2, 1, 0]),
+            })
+            .unwrap();
+        let (ranking, posterior, delta) = events
+            .into_iter()
+            .find_map(|event| match event {
+                Event::RoundResult {
+                    ranking,
+                    posterior_prediction,
+                    score_delta,
+                    ..
+                } => Some((ranking, posterior_prediction, score_delta)),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(ranking, vec![4, 3, 2, 1, 0]);
+        assert_eq!(posterior, ranking);
+        // 0 号末位 -2，后验全中 +2
+        assert_eq!(delta[0], 0);
+        assert_eq!(game.players[0].posterior_prediction, None);
+    }
+}
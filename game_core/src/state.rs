@@ -3,10 +3,13 @@ use crate::Suit;
 use crate::card::Card;
 use crate::command::Command;
 use crate::event::Event;
-use rand::rng;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
+use serde::Deserialize;
+use serde::Serialize;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlayerState {
     pub id: usize,                                // 玩家 ID
     pub is_first: bool,                           // 是否为首位玩家
@@ -18,7 +21,7 @@ pub struct PlayerState {
     pub posterior_prediction: Option<Vec<usize>>, // 后验预测,按从预测排名高到低顺序记录玩家 ID,不记录为none
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Phase {
     //Prediction, // 预测
     PriorPrediction,     // 先验预测
@@ -27,7 +30,154 @@ pub enum Phase {
     End,                 // 结束
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+// 桌上投票的议题，通过后由服务器执行
+pub enum Motion {
+    Pause,   // 暂停，行动计时停住
+    Resume,  // 继续
+    Abort,   // 中止，本局不计结果
+    Restart, // 对局结束后原班人马再来一局
+}
+
+impl Motion {
+    // 通过所需的赞成票：暂停 / 继续 / 重开过半即可，中止需要全体同意
+    pub fn needed(&self, voters: usize) -> usize {
+        match self {
+            Motion::Pause | Motion::Resume | Motion::Restart => voters / 2 + 1,
+            Motion::Abort => voters,
+        }
+    }
+}
+
+// 进行中的投票
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableVote {
+    pub id: u32, // 本局第几次投票，用于判断超时的是否还是同一次
+    pub motion: Motion,
+    pub ballots: Vec<Option<bool>>, // 按玩家 ID 排列，None 表示尚未表态
+}
+
+impl TableVote {
+    // 已成定局时返回是否通过
+    pub fn outcome(&self) -> Option<bool> {
+        let needed = self.motion.needed(self.ballots.len());
+        let yes = self.ballots.iter().filter(|b| **b == Some(true)).count();
+        let open = self.ballots.iter().filter(|b| b.is_none()).count();
+        if yes >= needed {
+            Some(true)
+        } else if yes + open < needed {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+// 一局游戏的规则
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameRules {
+    pub rounds: u8, // 总轮数（每人 5 张手牌，最多 5 轮）
+    #[serde(default)]
+    pub timers: TurnTimers, // 各阶段行动时限
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        GameRules {
+            rounds: 5,
+            timers: TurnTimers::default(),
+        }
+    }
+}
+
+impl GameRules {
+    // 规则校验
+    pub fn validate(&self) -> Result<(), String> {
+        if self.rounds == 0 || self.rounds > 5 {
+            return Err(format!("Invalid rounds: {}, expected 1..=5", self.rounds));
+        }
+        self.timers.validate()
+    }
+
+    pub const PRESETS: [&str; 3] = ["standard", "quick", "untimed"];
+
+    // 预设规则：standard 为默认规则，quick 轮数少、时限短，untimed 不限时
+    pub fn preset(name: &str) -> Option<GameRules> {
+        let rules = match name {
+            "standard" => GameRules::default(),
+            "quick" => GameRules {
+                rounds: 3,
+                timers: TurnTimers {
+                    prior_prediction: 15,
+                    play: 15,
+                    posterior_prediction: 30,
+                },
+            },
+            "untimed" => GameRules {
+                rounds: 5,
+                timers: TurnTimers::uniform(0),
+            },
+            _ => return None,
+        };
+        Some(rules)
+    }
+}
+
+// 各阶段的行动时限（秒），0 表示不限时
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TurnTimers {
+    pub prior_prediction: u32,
+    pub play: u32,
+    pub posterior_prediction: u32,
+}
+
+impl Default for TurnTimers {
+    fn default() -> Self {
+        TurnTimers {
+            prior_prediction: 30,
+            play: 30,
+            posterior_prediction: 60,
+        }
+    }
+}
+
+impl TurnTimers {
+    pub const MAX_SECS: u32 = 600;
+
+    // 所有阶段统一时限
+    pub fn uniform(secs: u32) -> Self {
+        TurnTimers {
+            prior_prediction: secs,
+            play: secs,
+            posterior_prediction: secs,
+        }
+    }
+
+    // 某阶段的时限，0 表示不限时
+    pub fn limit(&self, phase: Phase) -> u32 {
+        match phase {
+            Phase::PriorPrediction => self.prior_prediction,
+            Phase::Play => self.play,
+            Phase::PosteriorPrediction => self.posterior_prediction,
+            Phase::End => 0,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for secs in [self.prior_prediction, self.play, self.posterior_prediction] {
+            if secs > Self::MAX_SECS {
+                return Err(format!(
+                    "Invalid timer: {}s, expected at most {}s",
+                    secs,
+                    Self::MAX_SECS
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GameState {
     pub players: Vec<PlayerState>, // 玩家状态列表
     pub round: u8,                 // 当前轮数
@@ -36,11 +186,49 @@ pub struct GameState {
     pub current_player: usize,     // 当前行动玩家 ID
     pub table: Vec<(usize, Card)>, // 本轮牌桌上的牌（玩家 ID，牌）
     pub is_card: bool,             // 是否发牌
+    pub rules: GameRules,          // 本局规则
+    #[serde(default)]
+    pub vote: Option<TableVote>, // 进行中的投票
+    #[serde(default)]
+    pub votes_held: u32, // 本局已发起的投票数
 }
 
 impl GameState {
+    // 为 player_id 生成当前状态快照（用于断线重连）
+    pub fn snapshot(&self, player_id: usize) -> Option<Event> {
+        let player = self.players.iter().find(|p| p.id == player_id)?;
+
+        Some(Event::Snapshot {
+            player_id,
+            hand: player.hand.clone(),
+            scores: self.players.iter().map(|p| p.score).collect(),
+            round: self.round,
+            phase: self.phase,
+            start_player: self.start_player,
+            current_player: self.current_player,
+        })
+    }
+
+    // 观众看到的状态快照
+    pub fn public_snapshot(&self) -> Event {
+        Event::PublicSnapshot {
+            scores: self.players.iter().map(|p| p.score).collect(),
+            round: self.round,
+            phase: self.phase,
+            start_player: self.start_player,
+            current_player: self.current_player,
+            hand_sizes: self.players.iter().map(|p| p.hand.len()).collect(),
+            played: self.table.iter().map(|(id, _)| *id).collect(),
+        }
+    }
+
     pub fn deal_cards(&mut self) -> Vec<Event> {
-        let mut rng = rng(); // 使用线程安全的随机数生成器
+        self.deal_cards_seeded(rand::random())
+    }
+
+    // 按给定种子洗牌发牌，同一种子发出的牌相同，便于复盘
+    pub fn deal_cards_seeded(&mut self, seed: u64) -> Vec<Event> {
+        let mut rng = StdRng::seed_from_u64(seed);
 
         // 固定牌堆内容
         let mut small_deck: Vec<Card> = vec![
@@ -287,4 +475,63 @@ impl GameState {
 
         events // 返回发牌事件
     }
+
+    // 按记下的发牌事件还原手牌；恢复对局时用，不依赖随机数的实现
+    pub fn deal_recorded(&mut self, events: &[Event]) -> Result<Vec<Event>, String> {
+        let mut dealt = vec![];
+        for event in events {
+            if let Event::CardsDealt { player_id, cards } = event {
+                let Some(player) = self.players.get_mut(*player_id) else {
+                    return Err(format!("Cards dealt to unknown player {}", player_id));
+                };
+                player.hand = cards.clone();
+                dealt.push(event.clone());
+            }
+        }
+        if dealt.len() != self.players.len() {
+            return Err("Missing dealt cards".to_string());
+        }
+        Ok(dealt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vote(motion: Motion, ballots: &[Option<bool>]) -> TableVote {
+        TableVote {
+            id: 1,
+            motion,
+            ballots: ballots.to_vec(),
+        }
+    }
+
+    #[test]
+    fn outcome_decided_before_everyone_votes() {
+        // 5 人过半需 3 票
+        let yes = vote(
+            Motion::Pause,
+            &[Some(true), Some(true), Some(true), None, None],
+        );
+        assert_eq!(yes.outcome(), Some(true));
+        let no = vote(
+            Motion::Pause,
+            &[Some(false), Some(false), Some(false), None, None],
+        );
+        assert_eq!(no.outcome(), Some(false));
+        let open = vote(
+            Motion::Pause,
+            &[Some(true), Some(true), Some(false), None, None],
+        );
+        assert_eq!(open.outcome(), None);
+    }
+
+    #[test]
+    fn abort_fails_on_the_first_no() {
+        let abort = vote(Motion::Abort, &[Some(true), Some(false), None, None]);
+        assert_eq!(abort.outcome(), Some(false));
+        let abort = vote(Motion::Abort, &[Some(true), Some(true), None, None]);
+        assert_eq!(abort.outcome(), None);
+    }
 }
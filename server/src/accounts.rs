@@ -0,0 +1,93 @@
+use crate::storage::Storage;
+use argon2::Argon2;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use serde::{Deserialize, Serialize};
+
+// 注册账号：对局结果记在账号名下，而不是每局都会变的座位号
+// 不登录就是游客，照常可以游戏，只是不留统计
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Account {
+    pub id: i64,
+    pub username: String,
+    // 还没打过计分对局时为 None，按初始分算；登录和入座时读取，对局结束后更新
+    #[serde(default)]
+    pub rating: Option<f64>,
+}
+
+pub const USERNAME_LEN: std::ops::RangeInclusive<usize> = 3..=20;
+pub const PASSWORD_LEN: std::ops::RangeInclusive<usize> = 8..=128;
+
+// 用户名只允许字母、数字、下划线和连字符，比较时不区分大小写
+fn validate_username(username: &str) -> Result<(), String> {
+    if !USERNAME_LEN.contains(&username.len()) {
+        return Err(format!(
+            "Invalid username: expected {} to {} characters",
+            USERNAME_LEN.start(),
+            USERNAME_LEN.end()
+        ));
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err("Invalid username: use letters, digits, '_' or '-'".to_string());
+    }
+    Ok(())
+}
+
+fn validate_password(password: &str) -> Result<(), String> {
+    if !PASSWORD_LEN.contains(&password.chars().count()) {
+        return Err(format!(
+            "Invalid password: expected {} to {} characters",
+            PASSWORD_LEN.start(),
+            PASSWORD_LEN.end()
+        ));
+    }
+    Ok(())
+}
+
+// Argon2id，盐随机生成，和参数一起存在 PHC 格式的字符串里
+fn hash_password(password: &str) -> String {
+    let salt: [u8; 16] = rand::random();
+    let salt = SaltString::encode_b64(&salt).unwrap();
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .unwrap()
+        .to_string()
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+// 以下两个函数要算哈希，比较慢，调用方放到阻塞线程里执行
+pub fn register(storage: &Storage, username: &str, password: &str) -> Result<Account, String> {
+    validate_username(username)?;
+    validate_password(password)?;
+
+    let id = storage.create_account(username, &hash_password(password))?;
+    Ok(Account {
+        id,
+        username: username.to_string(),
+        rating: None,
+    })
+}
+
+// 用户名不存在和密码错误给出同样的提示
+pub fn login(storage: &Storage, username: &str, password: &str) -> Result<Account, String> {
+    const WRONG: &str = "Wrong username or password";
+
+    let Some((account, hash)) = storage.find_account(username)? else {
+        // 照样算一次哈希，免得从耗时上看出用户名是否存在
+        hash_password(password);
+        return Err(WRONG.to_string());
+    };
+    if !verify_password(password, &hash) {
+        return Err(WRONG.to_string());
+    }
+    Ok(account)
+}
@@ -0,0 +1,297 @@
+use crate::lobby::Lobby;
+use crate::metrics::METRICS;
+use crate::room::{Room, ServerPhase};
+use axum::extract::{Path, Query, State};
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use game_core::*;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::net::TcpListener;
+use tracing::error;
+
+// 管理与观察接口：与游戏协议共用同一个大厅
+// 没有鉴权，默认只绑定本机地址
+#[derive(Clone)]
+struct AdminState {
+    lobby: Arc<Lobby>,
+    started: Instant,
+}
+
+pub async fn serve(listener: TcpListener, lobby: Arc<Lobby>) {
+    let state = AdminState {
+        lobby,
+        started: Instant::now(),
+    };
+    let app = Router::new()
+        .route("/health", get(health))
+        .route("/rooms", get(list_rooms))
+        .route("/rooms/{room_id}", get(room_detail))
+        .route("/rooms/{room_id}/pause", post(pause))
+        .route("/rooms/{room_id}/resume", post(resume))
+        .route("/rooms/{room_id}/abort", post(abort))
+        .route("/rooms/{room_id}/seats/{seat}/kick", post(kick))
+        .route("/players", get(list_players))
+        .route("/leaderboard", get(leaderboard))
+        .route("/accounts/{username}/stats", get(account_stats))
+        .route("/metrics", get(metrics))
+        .with_state(state);
+
+    if let Err(err) = axum::serve(listener, app).await {
+        error!(error = %err, "admin API stopped");
+    }
+}
+
+/* ================= 返回的数据 ================= */
+
+#[derive(Serialize)]
+struct Health {
+    status: &'static str,
+    uptime_secs: u64,
+    rooms: usize,
+    players_connected: usize,
+}
+
+#[derive(Serialize)]
+struct RoomSummary {
+    #[serde(flatten)]
+    info: RoomInfo,
+    private: bool,
+    phase: ServerPhase,
+    paused: bool,
+}
+
+#[derive(Serialize)]
+struct RoomDetail {
+    #[serde(flatten)]
+    summary: RoomSummary,
+    seating: Vec<SeatStatus>,
+    game: Option<GameStatus>, // 开局后才有
+    score_history: Vec<Vec<i32>>,
+}
+
+#[derive(Serialize)]
+struct SeatStatus {
+    seat: usize,
+    occupant: &'static str, // human / bot / empty
+    connected: bool,
+    ready: bool,
+    host: bool,
+    muted: bool,
+    username: Option<String>, // 登录玩家的账号
+}
+
+// 对局的公开部分，不含手牌
+#[derive(Serialize)]
+struct GameStatus {
+    round: u8,
+    phase: Phase,
+    start_player: usize,
+    current_player: usize,
+    actor: Option<usize>,
+    scores: Vec<i32>,
+    played: Vec<usize>, // 本轮已出牌的玩家
+}
+
+#[derive(Serialize)]
+struct PlayerStatus {
+    room_id: u32,
+    seat: usize,
+    connected: bool,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (
+        status,
+        Json(ErrorBody {
+            error: message.to_string(),
+        }),
+    )
+        .into_response()
+}
+
+/* ================= 查询 ================= */
+
+async fn health(State(state): State<AdminState>) -> Json<Health> {
+    let rooms = state.lobby.rooms().await;
+    let mut players_connected = 0;
+    for room in &rooms {
+        players_connected += room.seating.lock().await.conns.len();
+    }
+
+    Json(Health {
+        status: "ok",
+        uptime_secs: state.started.elapsed().as_secs(),
+        rooms: rooms.len(),
+        players_connected,
+    })
+}
+
+async fn metrics(State(state): State<AdminState>) -> Response {
+    let rooms = state.lobby.rooms().await.len();
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        METRICS.render(rooms),
+    )
+        .into_response()
+}
+
+async fn summary(room: &Room) -> RoomSummary {
+    let phase = *room.phase.lock().await;
+    RoomSummary {
+        info: room.info().await,
+        private: room.is_private(),
+        phase,
+        paused: room.is_paused(),
+    }
+}
+
+async fn list_rooms(State(state): State<AdminState>) -> Json<Vec<RoomSummary>> {
+    let mut rooms = vec![];
+    for room in state.lobby.rooms().await {
+        rooms.push(summary(&room).await);
+    }
+    Json(rooms)
+}
+
+async fn room_detail(State(state): State<AdminState>, Path(room_id): Path<u32>) -> Response {
+    let Some(room) = state.lobby.get(room_id).await else {
+        return error(StatusCode::NOT_FOUND, "No such room");
+    };
+
+    let summary = summary(&room).await;
+    let seating = {
+        let seating = room.seating.lock().await;
+        let humans: Vec<usize> = seating.sessions.values().copied().collect();
+        (0..room.seats)
+            .map(|seat| SeatStatus {
+                seat,
+                occupant: if seating.bots.contains(&seat) {
+                    "bot"
+                } else if humans.contains(&seat) {
+                    "human"
+                } else {
+                    "empty"
+                },
+                connected: seating.conns.contains_key(&seat),
+                ready: seating.ready.contains(&seat),
+                host: seating.host == Some(seat),
+                muted: seating.muted.contains(&seat),
+                username: seating.account_of(seat).map(|a| a.username.clone()),
+            })
+            .collect()
+    };
+    let game = if summary.phase == ServerPhase::Waiting {
+        None
+    } else {
+        let game = room.game.lock().await;
+        Some(GameStatus {
+            round: game.round,
+            phase: game.phase,
+            start_player: game.start_player,
+            current_player: game.current_player,
+            actor: game.actor(),
+            scores: game.players.iter().map(|p| p.score).collect(),
+            played: game.table.iter().map(|(id, _)| *id).collect(),
+        })
+    };
+    let score_history = room.score_history.lock().await.clone();
+
+    Json(RoomDetail {
+        summary,
+        seating,
+        game,
+        score_history,
+    })
+    .into_response()
+}
+
+async fn list_players(State(state): State<AdminState>) -> Json<Vec<PlayerStatus>> {
+    let mut players = vec![];
+    for room in state.lobby.rooms().await {
+        let seating = room.seating.lock().await;
+        let mut seats: Vec<usize> = seating.sessions.values().copied().collect();
+        seats.sort();
+        players.extend(seats.into_iter().map(|seat| PlayerStatus {
+            room_id: room.id,
+            seat,
+            connected: seating.conns.contains_key(&seat),
+        }));
+    }
+    Json(players)
+}
+
+#[derive(Deserialize)]
+struct LeaderboardQuery {
+    limit: Option<usize>,
+}
+
+async fn leaderboard(
+    State(state): State<AdminState>,
+    Query(query): Query<LeaderboardQuery>,
+) -> Response {
+    match state.lobby.leaderboard(query.limit).await {
+        Ok(players) => Json(players).into_response(),
+        Err(err) => error(StatusCode::INTERNAL_SERVER_ERROR, &err),
+    }
+}
+
+async fn account_stats(State(state): State<AdminState>, Path(username): Path<String>) -> Response {
+    match state.lobby.player_stats(username).await {
+        Ok(Some(stats)) => Json(stats).into_response(),
+        Ok(None) => error(StatusCode::NOT_FOUND, "No finished games for that account"),
+        Err(err) => error(StatusCode::INTERNAL_SERVER_ERROR, &err),
+    }
+}
+
+/* ================= 操作 ================= */
+
+async fn pause(State(state): State<AdminState>, Path(room_id): Path<u32>) -> Response {
+    set_paused(&state, room_id, true).await
+}
+
+async fn resume(State(state): State<AdminState>, Path(room_id): Path<u32>) -> Response {
+    set_paused(&state, room_id, false).await
+}
+
+async fn set_paused(state: &AdminState, room_id: u32, paused: bool) -> Response {
+    let Some(room) = state.lobby.get(room_id).await else {
+        return error(StatusCode::NOT_FOUND, "No such room");
+    };
+    match room.set_paused(paused).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => error(StatusCode::CONFLICT, &err),
+    }
+}
+
+async fn abort(State(state): State<AdminState>, Path(room_id): Path<u32>) -> Response {
+    match state
+        .lobby
+        .abort(room_id, "Closed by an administrator")
+        .await
+    {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => error(StatusCode::NOT_FOUND, &err),
+    }
+}
+
+async fn kick(
+    State(state): State<AdminState>,
+    Path((room_id, seat)): Path<(u32, usize)>,
+) -> Response {
+    let Some(room) = state.lobby.get(room_id).await else {
+        return error(StatusCode::NOT_FOUND, "No such room");
+    };
+    match room.kick(seat, "Kicked by an administrator").await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => error(StatusCode::NOT_FOUND, &err),
+    }
+}
@@ -0,0 +1,130 @@
+use crate::outbox::Outbox;
+use game_core::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// 解说视角：能看到所有手牌和每条指令（含预测的具体内容），但延后若干轮才放出
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CastConfig {
+    pub key: Option<String>,  // 观看需要的密钥，未设置则不开放
+    pub min_delay_rounds: u8, // 客户端可以要求更长的延迟，但不能更短
+}
+
+impl Default for CastConfig {
+    fn default() -> Self {
+        CastConfig {
+            key: None,
+            min_delay_rounds: 1,
+        }
+    }
+}
+
+impl CastConfig {
+    // 校验密钥，返回实际使用的延迟轮数
+    pub fn authorize(&self, key: &str, delay_rounds: Option<u8>) -> Result<u8, String> {
+        match &self.key {
+            None => Err("Casting is disabled".to_string()),
+            Some(expected) if expected != key => Err("Wrong cast key".to_string()),
+            Some(_) => Ok(delay_rounds
+                .unwrap_or(self.min_delay_rounds)
+                .max(self.min_delay_rounds)),
+        }
+    }
+}
+
+struct Viewer {
+    outbox: Outbox,
+    delay_rounds: u8,
+    sent: usize, // 已发给该观众的条数
+}
+
+// 本局的完整事件流及各解说观众的进度
+// 每条记录带上发生时的轮数，当前轮数超过它加上延迟后才发出；对局结束后全部放出
+#[derive(Default)]
+pub struct CastLog {
+    steps: Vec<(u8, NetMessage)>,
+    viewers: HashMap<u64, Viewer>, // 连接编号 -> 观众
+}
+
+impl CastLog {
+    // 新开一局
+    pub fn clear(&mut self) {
+        self.steps.clear();
+        for viewer in self.viewers.values_mut() {
+            viewer.sent = 0;
+        }
+    }
+
+    pub fn push(&mut self, round: u8, command: Option<Command>, events: Vec<Event>) {
+        self.steps.push((
+            round,
+            NetMessage::CastStep {
+                round,
+                command,
+                events,
+            },
+        ));
+    }
+
+    // 停机快照里保存的事件流，还原后新来的解说观众仍能从本局开头看起
+    pub fn steps(&self) -> Vec<NetMessage> {
+        self.steps.iter().map(|(_, step)| step.clone()).collect()
+    }
+
+    pub fn from_steps(steps: Vec<NetMessage>) -> Self {
+        let steps = steps
+            .into_iter()
+            .filter_map(|step| match step {
+                NetMessage::CastStep { round, .. } => Some((round, step)),
+                _ => None,
+            })
+            .collect();
+        CastLog {
+            steps,
+            viewers: HashMap::new(),
+        }
+    }
+
+    // 新观众从本局开头补发已到时的部分
+    pub fn add_viewer(&mut self, conn_id: u64, outbox: Outbox, delay_rounds: u8, game: &GameState) {
+        self.viewers.insert(
+            conn_id,
+            Viewer {
+                outbox,
+                delay_rounds,
+                sent: 0,
+            },
+        );
+        self.release(game);
+    }
+
+    pub fn remove_viewer(&mut self, conn_id: u64) {
+        self.viewers.remove(&conn_id);
+    }
+
+    pub fn viewers(&self) -> usize {
+        self.viewers.len()
+    }
+
+    pub fn drain_viewers(&mut self) -> Vec<Outbox> {
+        self.viewers
+            .drain()
+            .map(|(_, viewer)| viewer.outbox)
+            .collect()
+    }
+
+    // 按当前进度发出到时的记录
+    pub fn release(&mut self, game: &GameState) {
+        let ended = game.phase == Phase::End;
+        for viewer in self.viewers.values_mut() {
+            while let Some((round, step)) = self.steps.get(viewer.sent) {
+                if !ended && round.saturating_add(viewer.delay_rounds) > game.round {
+                    break;
+                }
+                viewer.outbox.send(step);
+                viewer.sent += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,427 @@
+use crate::cast::CastConfig;
+use crate::heartbeat::HeartbeatConfig;
+use crate::journal::{FsyncPolicy, JournalConfig};
+use crate::ratelimit::LimitsConfig;
+use crate::rating::RatingConfig;
+use crate::room::MAX_SEATS;
+use crate::snapshot::RestorePolicy;
+use clap::Parser;
+use game_core::*;
+use logging::{LogFormat, LogLevel};
+use serde::Deserialize;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const DEFAULT_CONFIG: &str = "server.toml"; // 未指定 --config 时，存在则读取
+
+// 命令行参数，优先于配置文件
+#[derive(Debug, Parser)]
+#[command(name = "server", about = "Card game server")]
+pub struct Args {
+    /// Path to the TOML config file [default: server.toml if present]
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+    /// Address to listen on
+    #[arg(long)]
+    pub address: Option<String>,
+    /// Port to listen on
+    #[arg(short, long)]
+    pub port: Option<u16>,
+    /// WebSocket port, 0 to disable
+    #[arg(long)]
+    pub ws_port: Option<u16>,
+    /// Address for the HTTP admin API
+    #[arg(long)]
+    pub admin_address: Option<String>,
+    /// Port for the HTTP admin API, 0 to disable
+    #[arg(long)]
+    pub admin_port: Option<u16>,
+    /// Log level
+    #[arg(long, value_enum)]
+    pub log_level: Option<LogLevel>,
+    /// Log output format
+    #[arg(long, value_enum)]
+    pub log_format: Option<LogFormat>,
+    /// Directory for persistent data
+    #[arg(long)]
+    pub data_dir: Option<PathBuf>,
+    /// Default seat count for new rooms
+    #[arg(long)]
+    pub seats: Option<usize>,
+    /// Default rule preset for new rooms (standard, quick, untimed)
+    #[arg(long)]
+    pub preset: Option<String>,
+    /// Default number of rounds, overrides the preset
+    #[arg(long)]
+    pub rounds: Option<u8>,
+    /// Default turn time limit in seconds for every phase, 0 for unlimited
+    #[arg(long)]
+    pub timer: Option<u32>,
+    /// Seconds before bots fill a waiting room
+    #[arg(long)]
+    pub waiting_timeout: Option<u64>,
+    /// Seconds between heartbeats
+    #[arg(long)]
+    pub heartbeat_interval: Option<u64>,
+    /// Missed heartbeats before a connection is dropped
+    #[arg(long)]
+    pub heartbeat_missed: Option<u32>,
+    /// Disable the write-ahead game journal
+    #[arg(long)]
+    pub no_journal: bool,
+    /// When to fsync the game journal
+    #[arg(long, value_enum)]
+    pub fsync: Option<FsyncPolicy>,
+    /// Seconds between the shutdown notice and disconnecting players
+    #[arg(long)]
+    pub shutdown_grace: Option<u64>,
+    /// What to do with rooms saved at the last shutdown
+    #[arg(long, value_enum)]
+    pub restore: Option<RestorePolicy>,
+    /// Do not read operator commands from stdin
+    #[arg(long)]
+    pub no_console: bool,
+    /// Maximum simultaneous connections from one address, 0 for unlimited
+    #[arg(long)]
+    pub max_connections_per_ip: Option<u32>,
+    /// Prefer rooms with similar ratings when quick-joining
+    #[arg(long)]
+    pub matchmaking: bool,
+}
+
+/* ================= 配置文件 ================= */
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub address: String,
+    pub port: u16,
+    pub websocket_port: u16,   // 与 TCP 共用地址，0 表示不开
+    pub admin_address: String, // 管理接口没有鉴权，默认只绑定本机
+    pub admin_port: u16,       // 0 表示不开
+    pub log_level: LogLevel,
+    pub log_format: LogFormat,
+    pub data_dir: PathBuf,
+    pub console: bool, // 在终端上运行时从标准输入读运维命令
+    pub room: RoomSection,
+    pub heartbeat: HeartbeatSection,
+    pub journal: JournalSection,
+    pub shutdown: ShutdownSection,
+    pub cast: CastConfig, // 密钥只在配置文件里设置，不出现在命令行上
+    pub limits: LimitsConfig,
+    pub rating: RatingConfig,
+}
+
+// 新建房间时客户端未指定的参数
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoomSection {
+    pub seats: usize,
+    pub preset: String,
+    pub rounds: Option<u8>,         // 覆盖预设的轮数
+    pub timers: Option<TurnTimers>, // 覆盖预设的时限
+    pub waiting_timeout_secs: u64,  // 等人超时后由机器人补位
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeartbeatSection {
+    pub interval_secs: u64,
+    pub max_missed: u32,
+}
+
+// 预写日志：<data_dir>/journal/room-<id>.jsonl
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JournalSection {
+    pub enabled: bool,
+    pub fsync: FsyncPolicy,
+    pub fsync_interval_ms: u64, // fsync = "interval" 时的间隔
+}
+
+// 停机：通知玩家后等待宽限期，再把进行中的房间存为 <data_dir>/snapshot.json
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownSection {
+    pub grace_secs: u64,
+    pub restore: RestorePolicy, // 下次启动时如何处理快照
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            address: "0.0.0.0".to_string(),
+            port: 9000,
+            websocket_port: 9001,
+            admin_address: "127.0.0.1".to_string(),
+            admin_port: 9080,
+            log_level: LogLevel::Info,
+            log_format: LogFormat::Text,
+            data_dir: PathBuf::from("data"),
+            console: true,
+            room: RoomSection::default(),
+            heartbeat: HeartbeatSection::default(),
+            journal: JournalSection::default(),
+            shutdown: ShutdownSection::default(),
+            cast: CastConfig::default(),
+            limits: LimitsConfig::default(),
+            rating: RatingConfig::default(),
+        }
+    }
+}
+
+impl Default for RoomSection {
+    fn default() -> Self {
+        RoomSection {
+            seats: MAX_SEATS,
+            preset: "standard".to_string(),
+            rounds: None,
+            timers: None,
+            waiting_timeout_secs: 120,
+        }
+    }
+}
+
+impl Default for JournalSection {
+    fn default() -> Self {
+        JournalSection {
+            enabled: true,
+            fsync: FsyncPolicy::Always,
+            fsync_interval_ms: 1000,
+        }
+    }
+}
+
+impl Default for ShutdownSection {
+    fn default() -> Self {
+        ShutdownSection {
+            grace_secs: 10,
+            restore: RestorePolicy::Ask,
+        }
+    }
+}
+
+impl Default for HeartbeatSection {
+    fn default() -> Self {
+        let heartbeat = HeartbeatConfig::default();
+        HeartbeatSection {
+            interval_secs: heartbeat.interval.as_secs(),
+            max_missed: heartbeat.max_missed,
+        }
+    }
+}
+
+// 新建房间的默认参数（已校验）
+#[derive(Debug, Clone, Copy)]
+pub struct RoomDefaults {
+    pub seats: usize,
+    pub rules: GameRules,
+    pub waiting_timeout: Duration,
+}
+
+impl Config {
+    // 读取配置文件并叠加命令行参数，最后统一校验
+    pub fn load(args: Args) -> Result<Config, String> {
+        let mut config = match &args.config {
+            Some(path) => Config::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG).exists() => {
+                Config::from_file(Path::new(DEFAULT_CONFIG))?
+            }
+            None => Config::default(),
+        };
+
+        if let Some(address) = args.address {
+            config.address = address;
+        }
+        if let Some(port) = args.port {
+            config.port = port;
+        }
+        if let Some(port) = args.ws_port {
+            config.websocket_port = port;
+        }
+        if let Some(address) = args.admin_address {
+            config.admin_address = address;
+        }
+        if let Some(port) = args.admin_port {
+            config.admin_port = port;
+        }
+        if let Some(log_level) = args.log_level {
+            config.log_level = log_level;
+        }
+        if let Some(log_format) = args.log_format {
+            config.log_format = log_format;
+        }
+        if let Some(data_dir) = args.data_dir {
+            config.data_dir = data_dir;
+        }
+        if let Some(seats) = args.seats {
+            config.room.seats = seats;
+        }
+        if let Some(preset) = args.preset {
+            config.room.preset = preset;
+        }
+        if let Some(rounds) = args.rounds {
+            config.room.rounds = Some(rounds);
+        }
+        if let Some(secs) = args.timer {
+            config.room.timers = Some(TurnTimers::uniform(secs));
+        }
+        if let Some(secs) = args.waiting_timeout {
+            config.room.waiting_timeout_secs = secs;
+        }
+        if let Some(secs) = args.heartbeat_interval {
+            config.heartbeat.interval_secs = secs;
+        }
+        if let Some(missed) = args.heartbeat_missed {
+            config.heartbeat.max_missed = missed;
+        }
+        if args.no_journal {
+            config.journal.enabled = false;
+        }
+        if let Some(fsync) = args.fsync {
+            config.journal.fsync = fsync;
+        }
+        if let Some(secs) = args.shutdown_grace {
+            config.shutdown.grace_secs = secs;
+        }
+        if let Some(restore) = args.restore {
+            config.shutdown.restore = restore;
+        }
+        if args.no_console {
+            config.console = false;
+        }
+        if let Some(max) = args.max_connections_per_ip {
+            config.limits.max_connections_per_ip = max;
+        }
+        if args.matchmaking {
+            config.rating.matchmaking = true;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Config, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read config file {}: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("Invalid config file {}: {}", path.display(), e))
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        self.listen_addr()?;
+        self.websocket_addr()?;
+        self.admin_addr()?;
+        self.room_defaults()?;
+        self.limits.validate()?;
+        self.rating.validate()?;
+
+        if self.room.waiting_timeout_secs == 0 {
+            return Err("Invalid room waiting_timeout_secs: must be at least 1s".to_string());
+        }
+        if self.heartbeat.interval_secs == 0 {
+            return Err("Invalid heartbeat interval: must be at least 1s".to_string());
+        }
+        if self.heartbeat.max_missed == 0 {
+            return Err("Invalid heartbeat max_missed: must be at least 1".to_string());
+        }
+        if self.journal.fsync == FsyncPolicy::Interval && self.journal.fsync_interval_ms == 0 {
+            return Err("Invalid journal fsync_interval_ms: must be at least 1".to_string());
+        }
+        if self
+            .cast
+            .key
+            .as_deref()
+            .is_some_and(|key| key.trim().is_empty())
+        {
+            return Err("Invalid cast key: must not be empty".to_string());
+        }
+        if self.data_dir.as_os_str().is_empty() {
+            return Err("Invalid data_dir: must not be empty".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn listen_addr(&self) -> Result<SocketAddr, String> {
+        socket_addr("listen", &self.address, self.port)
+    }
+
+    pub fn websocket_addr(&self) -> Result<Option<SocketAddr>, String> {
+        if self.websocket_port == 0 {
+            return Ok(None);
+        }
+        if self.websocket_port == self.port {
+            return Err(format!(
+                "Invalid websocket_port: {} is already the TCP port",
+                self.websocket_port
+            ));
+        }
+        socket_addr("WebSocket", &self.address, self.websocket_port).map(Some)
+    }
+
+    pub fn admin_addr(&self) -> Result<Option<SocketAddr>, String> {
+        if self.admin_port == 0 {
+            return Ok(None);
+        }
+        socket_addr("admin", &self.admin_address, self.admin_port).map(Some)
+    }
+
+    pub fn room_defaults(&self) -> Result<RoomDefaults, String> {
+        let room = &self.room;
+        if !(2..=MAX_SEATS).contains(&room.seats) {
+            return Err(format!(
+                "Invalid default seats: {}, expected 2..={}",
+                room.seats, MAX_SEATS
+            ));
+        }
+
+        let Some(mut rules) = GameRules::preset(&room.preset) else {
+            return Err(format!(
+                "Unknown rule preset: {}, expected one of {}",
+                room.preset,
+                GameRules::PRESETS.join(", ")
+            ));
+        };
+        if let Some(rounds) = room.rounds {
+            rules.rounds = rounds;
+        }
+        if let Some(timers) = room.timers {
+            rules.timers = timers;
+        }
+        rules
+            .validate()
+            .map_err(|e| format!("Invalid default rules: {}", e))?;
+
+        Ok(RoomDefaults {
+            seats: room.seats,
+            rules,
+            waiting_timeout: Duration::from_secs(room.waiting_timeout_secs),
+        })
+    }
+
+    pub fn heartbeat(&self) -> HeartbeatConfig {
+        HeartbeatConfig {
+            interval: Duration::from_secs(self.heartbeat.interval_secs),
+            max_missed: self.heartbeat.max_missed,
+        }
+    }
+
+    pub fn journal(&self) -> Option<JournalConfig> {
+        self.journal.enabled.then(|| JournalConfig {
+            dir: self.data_dir.join("journal"),
+            fsync: self.journal.fsync,
+            fsync_interval: Duration::from_millis(self.journal.fsync_interval_ms),
+        })
+    }
+
+    pub fn snapshot_path(&self) -> PathBuf {
+        self.data_dir.join("snapshot.json")
+    }
+}
+
+// 主机只接受 IP 地址，IPv6 不带方括号，如 ::1
+fn socket_addr(kind: &str, host: &str, port: u16) -> Result<SocketAddr, String> {
+    host.parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, port))
+        .map_err(|_| format!("Invalid {} address: {}", kind, host))
+}
@@ -0,0 +1,247 @@
+use crate::lobby::Lobby;
+use crate::room::{Room, ServerPhase};
+use crate::snapshot;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tracing::info;
+
+// 运维控制台：在终端上运行时从标准输入读命令，与网络连接共用同一个大厅
+// 输出直接打到标准输出，日志仍在标准错误
+pub async fn run(lobby: Arc<Lobby>, data_dir: PathBuf) {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    println!("Operator console ready, type 'help' for commands");
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        info!(command = line.trim(), "console command");
+        if let Err(err) = execute(&lobby, &data_dir, &line, &words).await {
+            println!("Error: {}", err);
+        }
+    }
+}
+
+async fn execute(lobby: &Lobby, data_dir: &Path, line: &str, words: &[&str]) -> Result<(), String> {
+    match words {
+        ["help"] => print_help(),
+        ["rooms"] => list_rooms(lobby).await,
+        ["players"] => list_players(lobby).await,
+        ["room", room_id] => {
+            let room = room(lobby, room_id).await?;
+            show_room(&room).await;
+        }
+        ["advance", room_id] => {
+            let seat = room(lobby, room_id).await?.force_advance().await?;
+            println!("Moved for seat {}", seat);
+        }
+        ["pause", room_id] => room(lobby, room_id).await?.set_paused(true).await?,
+        ["resume", room_id] => room(lobby, room_id).await?.set_paused(false).await?,
+        ["abort", room_id] => {
+            lobby
+                .abort(parse(room_id)?, "Closed by an administrator")
+                .await?
+        }
+        ["kick", room_id, seat, ..] => {
+            // 原因可以带空格
+            let reason = rest_of(line, 3).unwrap_or("Kicked by an administrator");
+            room(lobby, room_id)
+                .await?
+                .kick(parse(seat)?, reason)
+                .await?;
+        }
+        ["mute", room_id, seat] => {
+            room(lobby, room_id)
+                .await?
+                .set_muted(parse(seat)?, true)
+                .await?
+        }
+        ["unmute", room_id, seat] => {
+            room(lobby, room_id)
+                .await?
+                .set_muted(parse(seat)?, false)
+                .await?
+        }
+        ["notice", ..] => {
+            let text = rest_of(line, 1).ok_or("Empty notice")?;
+            lobby.notice(text).await;
+        }
+        ["snapshot"] => {
+            let snapshots = lobby.snapshot().await;
+            let secs = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let path = data_dir.join(format!("snapshot-{}.json", secs));
+            snapshot::save(&path, &snapshots)?;
+            println!("Saved {} game(s) to {}", snapshots.len(), path.display());
+        }
+        _ => return Err("Unknown command, type 'help'".to_string()),
+    }
+    Ok(())
+}
+
+fn print_help() {
+    println!("Commands:");
+    println!("  rooms                          list rooms");
+    println!("  players                        list seated players");
+    println!("  room <id>                      show a room's seats and game state");
+    println!("  advance <id>                   make the current move for the player to act");
+    println!("  pause / resume <id>            pause or resume a game");
+    println!("  abort <id>                     close a room, the game is not scored");
+    println!("  kick <id> <seat> [reason]      remove a player, a bot takes over in a game");
+    println!("  mute / unmute <id> <seat>      stop or allow a player's chat and emotes");
+    println!("  notice <text>                  send a notice to everyone in a room");
+    println!("  snapshot                       save running games to a timestamped file");
+    println!("                                 (rename it to snapshot.json to restore on start)");
+}
+
+fn parse<T: std::str::FromStr>(word: &str) -> Result<T, String> {
+    word.parse()
+        .map_err(|_| format!("Invalid number: {}", word))
+}
+
+async fn room(lobby: &Lobby, room_id: &str) -> Result<Arc<Room>, String> {
+    lobby
+        .get(parse(room_id)?)
+        .await
+        .ok_or_else(|| "No such room".to_string())
+}
+
+// 跳过前 n 个词后的原文，保留中间的空白
+fn rest_of(line: &str, n: usize) -> Option<&str> {
+    let mut rest = line.trim_start();
+    for _ in 0..n {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        rest = rest[end..].trim_start();
+    }
+    let rest = rest.trim_end();
+    (!rest.is_empty()).then_some(rest)
+}
+
+async fn list_rooms(lobby: &Lobby) {
+    let rooms = lobby.rooms().await;
+    if rooms.is_empty() {
+        println!("No rooms");
+        return;
+    }
+    for room in rooms {
+        let info = room.info().await;
+        let phase = *room.phase.lock().await;
+        println!(
+            "#{} {} [{:?}{}] {}/{} players, {} watching{}",
+            info.room_id,
+            info.name,
+            phase,
+            if room.is_paused() { ", paused" } else { "" },
+            info.players,
+            info.seats,
+            info.spectators,
+            room.invite_code
+                .as_deref()
+                .map(|code| format!(", code {}", code))
+                .unwrap_or_default()
+        );
+    }
+}
+
+async fn list_players(lobby: &Lobby) {
+    let mut any = false;
+    for room in lobby.rooms().await {
+        let seating = room.seating.lock().await;
+        let mut seats: Vec<usize> = seating.sessions.values().copied().collect();
+        seats.sort();
+        for seat in seats {
+            any = true;
+            println!(
+                "room #{} seat {}: {}{}{}",
+                room.id,
+                seat,
+                if seating.conns.contains_key(&seat) {
+                    "connected"
+                } else {
+                    "disconnected"
+                },
+                if seating.host == Some(seat) {
+                    ", host"
+                } else {
+                    ""
+                },
+                if seating.muted.contains(&seat) {
+                    ", muted"
+                } else {
+                    ""
+                },
+            );
+        }
+    }
+    if !any {
+        println!("No players");
+    }
+}
+
+async fn show_room(room: &Room) {
+    let phase = *room.phase.lock().await;
+    println!(
+        "#{} {} [{:?}{}]",
+        room.id,
+        room.name,
+        phase,
+        if room.is_paused() { ", paused" } else { "" }
+    );
+    {
+        let seating = room.seating.lock().await;
+        for seat in 0..room.seats {
+            let occupant = if seating.bots.contains(&seat) {
+                "bot"
+            } else if seating.token_of(seat).is_some() {
+                if seating.conns.contains_key(&seat) {
+                    "human"
+                } else {
+                    "human (disconnected)"
+                }
+            } else {
+                "empty"
+            };
+            let mut flags = vec![];
+            if seating.host == Some(seat) {
+                flags.push("host");
+            }
+            if seating.ready.contains(&seat) {
+                flags.push("ready");
+            }
+            if seating.muted.contains(&seat) {
+                flags.push("muted");
+            }
+            let name = seating
+                .account_of(seat)
+                .map(|account| format!(" ({})", account.username))
+                .unwrap_or_default();
+            println!("  seat {}: {}{} {}", seat, occupant, name, flags.join(" "));
+        }
+    }
+    if phase == ServerPhase::Waiting {
+        return;
+    }
+
+    let game = room.game.lock().await;
+    println!(
+        "  round {}/{}, {:?}, to act: {}",
+        game.round,
+        game.rules.rounds,
+        game.phase,
+        game.actor()
+            .map(|seat| seat.to_string())
+            .unwrap_or_else(|| "-".to_string())
+    );
+    let scores: Vec<i32> = game.players.iter().map(|p| p.score).collect();
+    println!("  scores: {:?}", scores);
+    let played: Vec<usize> = game.table.iter().map(|(id, _)| *id).collect();
+    println!("  played this round: {:?}", played);
+    if let Some(vote) = &game.vote {
+        println!("  vote to {:?}: {:?}", vote.motion, vote.ballots);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::outbox::Outbox;
+use crate::transport::{BadLine, LineReader};
+use game_core::*;
+use tokio::time::{Duration, Instant, Interval, interval_at};
+
+#[derive(Debug, Clone, Copy)]
+// 心跳参数：每隔 interval 发一次 Ping，连续 max_missed 个间隔收不到任何消息即断开
+pub struct HeartbeatConfig {
+    pub interval: Duration,
+    pub max_missed: u32,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        HeartbeatConfig {
+            interval: Duration::from_secs(10),
+            max_missed: 3,
+        }
+    }
+}
+
+// 读取连接的下一条输入
+pub enum Incoming {
+    Line(String),
+    Bad(BadLine), // 超长或无法解码的一行，同样算存活
+    Idle,         // 错过了一次心跳
+    Active,       // 空闲后重新收到消息（随后照常返回这条消息）
+    Closed,       // 连接断开或心跳超时
+}
+
+impl From<Result<String, BadLine>> for Incoming {
+    fn from(line: Result<String, BadLine>) -> Self {
+        match line {
+            Ok(line) => Incoming::Line(line),
+            Err(bad) => Incoming::Bad(bad),
+        }
+    }
+}
+
+// 单条连接的心跳状态；收到的任何一行（包括 Pong）都算存活
+pub struct Heartbeat {
+    config: HeartbeatConfig,
+    ticker: Interval,
+    heard: bool,
+    missed: u32,
+    idle: bool,
+    nonce: u64,
+    pending: Option<Result<String, BadLine>>,
+}
+
+impl Heartbeat {
+    pub fn new(config: HeartbeatConfig) -> Self {
+        // interval 的第一次 tick 是立即触发的，推迟一个间隔
+        let ticker = interval_at(Instant::now() + config.interval, config.interval);
+
+        Heartbeat {
+            config,
+            ticker,
+            heard: false,
+            missed: 0,
+            idle: false,
+            nonce: 0,
+            pending: None,
+        }
+    }
+
+    pub async fn next(&mut self, reader: &mut LineReader, outbox: &Outbox) -> Incoming {
+        if let Some(line) = self.pending.take() {
+            return Incoming::from(line);
+        }
+
+        loop {
+            tokio::select! {
+                line = reader.next_line() => {
+                    let Some(line) = line else {
+                        return Incoming::Closed;
+                    };
+                    self.heard = true;
+                    self.missed = 0;
+
+                    if self.idle {
+                        self.idle = false;
+                        self.pending = Some(line);
+                        return Incoming::Active;
+                    }
+                    return Incoming::from(line);
+                }
+                _ = self.ticker.tick() => {
+                    if self.heard {
+                        self.missed = 0;
+                    } else {
+                        self.missed += 1;
+                    }
+                    self.heard = false;
+
+                    if self.missed >= self.config.max_missed {
+                        outbox.close();
+                        return Incoming::Closed;
+                    }
+
+                    self.nonce += 1;
+                    outbox.send(&NetMessage::Ping { nonce: self.nonce });
+
+                    if self.missed > 0 && !self.idle {
+                        self.idle = true;
+                        return Incoming::Idle;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use crate::heartbeat::{Heartbeat, Incoming};
+use crate::outbox::Outbox;
+use crate::ratelimit::{IpSlot, LimitsConfig, TokenBucket};
+use crate::transport::{BadLine, LineReader};
+use game_core::*;
+use tracing::{info, warn};
+
+// 连接的读方向：心跳、长度和频率限制、解析
+// 超长、过快或无法解析的输入都算违规，同一地址违规过多会被临时封禁
+pub struct Inbound {
+    reader: LineReader,
+    heartbeat: Heartbeat,
+    messages: TokenBucket,
+    chat: TokenBucket,
+    slot: IpSlot,
+}
+
+pub enum Received {
+    Message(NetMessage),
+    Idle,   // 错过了一次心跳
+    Active, // 空闲后重新收到消息
+    Closed, // 连接断开、心跳超时或因违规被断开
+}
+
+impl Inbound {
+    pub fn new(
+        reader: LineReader,
+        heartbeat: Heartbeat,
+        limits: &LimitsConfig,
+        slot: IpSlot,
+    ) -> Self {
+        Inbound {
+            reader,
+            heartbeat,
+            messages: limits.message_bucket(),
+            chat: limits.chat_bucket(),
+            slot,
+        }
+    }
+
+    pub async fn next(&mut self, outbox: &Outbox) -> Received {
+        loop {
+            let problem = match self.heartbeat.next(&mut self.reader, outbox).await {
+                Incoming::Idle => return Received::Idle,
+                Incoming::Active => return Received::Active,
+                Incoming::Closed => return Received::Closed,
+                Incoming::Bad(BadLine::TooLong) => "Message too long",
+                Incoming::Bad(BadLine::NotUtf8) => "Invalid message",
+                Incoming::Line(_) if !self.messages.try_take() => "Sending messages too fast",
+                Incoming::Line(line) => match serde_json::from_str(&line) {
+                    Ok(msg) => return Received::Message(msg),
+                    Err(err) => {
+                        info!(error = %err, "invalid message");
+                        "Invalid message"
+                    }
+                },
+            };
+            if self.violation(outbox, problem) {
+                return Received::Closed;
+            }
+        }
+    }
+
+    // 聊天和表情另有更严的限制，超出只拒绝，不算违规
+    pub fn allow_chat(&mut self) -> bool {
+        self.chat.try_take()
+    }
+
+    // 回复错误并记一次违规；地址因此被封禁时断开连接并返回 true
+    pub fn violation(&self, outbox: &Outbox, problem: &str) -> bool {
+        info!(error = problem, "protocol violation");
+        outbox.send(&NetMessage::Error {
+            message: problem.to_string(),
+        });
+        if !self.slot.violation() {
+            return false;
+        }
+
+        warn!(ip = %self.slot.ip(), "address banned");
+        outbox.send(&NetMessage::Error {
+            message: "Too many invalid requests, temporarily banned".to_string(),
+        });
+        outbox.finish();
+        true
+    }
+}
@@ -0,0 +1,230 @@
+use crate::storage::{SavedGame, SeatRecord};
+use clap::ValueEnum;
+use game_core::*;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{error, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+// 何时把日志刷到磁盘
+pub enum FsyncPolicy {
+    Always,   // 每条都 fsync，最安全
+    Interval, // 距上次 fsync 超过间隔才 fsync，崩溃时可能丢最后一小段
+    Never,    // 交给操作系统
+}
+
+#[derive(Debug, Clone)]
+pub struct JournalConfig {
+    pub dir: PathBuf,
+    pub fsync: FsyncPolicy,
+    pub fsync_interval: Duration,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+// 日志中的一行；每行独立写入，崩溃时最多留下半行
+pub enum JournalEntry {
+    // 开局：恢复所需的全部信息和发牌事件
+    Started {
+        game_id: Option<i64>,
+        room_id: u32,
+        room_name: String,
+        invite_code: Option<String>,
+        #[serde(default)]
+        password: Option<String>,
+        seats: usize,
+        rules: GameRules,
+        seed: u64,
+        players: Vec<SeatRecord>,
+        events: Vec<Event>,
+    },
+    // 一条被接受的指令及其产生的事件
+    Step {
+        command: Command,
+        events: Vec<Event>,
+    },
+}
+
+// 单个房间的预写日志：指令和事件在广播前追加到 <dir>/room-<id>.jsonl
+// 对局结束后删除；重启时逐条重放以恢复房间
+pub struct Journal {
+    path: PathBuf,
+    file: Mutex<(File, Instant)>, // 文件及上次 fsync 的时间
+    config: JournalConfig,
+}
+
+impl Journal {
+    // 新开一局：覆盖同名的旧日志
+    pub fn create(
+        config: &JournalConfig,
+        room_id: u32,
+        entry: &JournalEntry,
+    ) -> Result<Self, String> {
+        let path = config.dir.join(format!("room-{}.jsonl", room_id));
+        let file = File::create(&path)
+            .map_err(|e| format!("Cannot create journal {}: {}", path.display(), e))?;
+
+        let journal = Journal {
+            path,
+            file: Mutex::new((file, Instant::now())),
+            config: config.clone(),
+        };
+        journal.append(entry)?;
+        Ok(journal)
+    }
+
+    pub fn append(&self, entry: &JournalEntry) -> Result<(), String> {
+        let line = serde_json::to_string(entry).unwrap() + "\n";
+        let mut guard = self.file.lock().unwrap();
+        let (file, last_sync) = &mut *guard;
+
+        file.write_all(line.as_bytes())
+            .map_err(|e| format!("Cannot write journal {}: {}", self.path.display(), e))?;
+
+        let sync = match self.config.fsync {
+            FsyncPolicy::Always => true,
+            FsyncPolicy::Interval => last_sync.elapsed() >= self.config.fsync_interval,
+            FsyncPolicy::Never => false,
+        };
+        if sync {
+            file.sync_data()
+                .map_err(|e| format!("Cannot sync journal {}: {}", self.path.display(), e))?;
+            *last_sync = Instant::now();
+        }
+        Ok(())
+    }
+
+    // 对局已结束，日志不再需要
+    pub fn remove(&self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            warn!(path = %self.path.display(), error = %err, "cannot remove journal");
+        }
+    }
+
+    // 读取目录下所有日志，返回可以继续追加的日志和待恢复的对局
+    // 读不了的日志改名为 .failed 留作排查，不再重试
+    pub fn load_all(config: &JournalConfig) -> Result<Vec<(Journal, SavedGame)>, String> {
+        fs::create_dir_all(&config.dir).map_err(|e| {
+            format!(
+                "Cannot create journal directory {}: {}",
+                config.dir.display(),
+                e
+            )
+        })?;
+        let entries = fs::read_dir(&config.dir).map_err(|e| {
+            format!(
+                "Cannot read journal directory {}: {}",
+                config.dir.display(),
+                e
+            )
+        })?;
+
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+            .collect();
+        paths.sort();
+
+        let mut loaded = vec![];
+        for path in paths {
+            match Journal::load(config, &path) {
+                Ok(journal) => loaded.push(journal),
+                Err(err) => {
+                    error!(path = %path.display(), error = %err, "cannot load journal");
+                    Journal::discard(&path);
+                }
+            }
+        }
+        Ok(loaded)
+    }
+
+    fn load(config: &JournalConfig, path: &Path) -> Result<(Journal, SavedGame), String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Cannot read: {}", e))?;
+
+        // 只有最后一行允许不完整（写到一半崩溃），截掉后继续追加
+        let mut good_len = 0;
+        let mut entries = vec![];
+        let lines: Vec<&str> = text.split_inclusive('\n').collect();
+        for (i, line) in lines.iter().enumerate() {
+            match serde_json::from_str::<JournalEntry>(line) {
+                Ok(entry) if line.ends_with('\n') => {
+                    entries.push(entry);
+                    good_len += line.len();
+                }
+                _ if i + 1 == lines.len() => break,
+                _ => return Err(format!("Corrupt entry at line {}", i + 1)),
+            }
+        }
+
+        let mut entries = entries.into_iter();
+        let Some(JournalEntry::Started {
+            game_id,
+            room_id,
+            room_name,
+            invite_code,
+            password,
+            seats,
+            rules,
+            players,
+            events,
+            ..
+        }) = entries.next()
+        else {
+            return Err("Missing start entry".to_string());
+        };
+
+        let mut commands = vec![];
+        for entry in entries {
+            match entry {
+                JournalEntry::Step { command, .. } => commands.push(command),
+                JournalEntry::Started { .. } => return Err("Duplicate start entry".to_string()),
+            }
+        }
+
+        let file = OpenOptions::new()
+            .write(true)
+            .open(path)
+            .map_err(|e| format!("Cannot open: {}", e))?;
+        file.set_len(good_len as u64)
+            .map_err(|e| format!("Cannot truncate: {}", e))?;
+        let file = OpenOptions::new()
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Cannot open: {}", e))?;
+
+        let journal = Journal {
+            path: path.to_path_buf(),
+            file: Mutex::new((file, Instant::now())),
+            config: config.clone(),
+        };
+        let saved = SavedGame {
+            game_id,
+            room_id,
+            room_name,
+            invite_code,
+            password,
+            seats,
+            rules,
+            players,
+            dealt: events,
+            commands,
+        };
+        Ok((journal, saved))
+    }
+
+    // 无法恢复的日志改名保留
+    pub fn discard(path: &Path) {
+        let failed = path.with_extension("jsonl.failed");
+        if let Err(err) = fs::rename(path, &failed) {
+            warn!(path = %path.display(), error = %err, "cannot rename journal");
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
@@ -0,0 +1,446 @@
+use crate::accounts::{self, Account};
+use crate::cast::CastConfig;
+use crate::config::RoomDefaults;
+use crate::journal::Journal;
+use crate::room::{MAX_SEATS, Room, ServerPhase};
+use crate::snapshot::RoomSnapshot;
+use crate::stats::{LEADERBOARD_DEFAULT, LEADERBOARD_MAX};
+use crate::storage::Storage;
+use game_core::*;
+use rand::seq::IndexedRandom;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+// 大厅：管理所有房间
+pub struct Lobby {
+    rooms: Mutex<HashMap<u32, Arc<Room>>>,
+    next_room_id: Mutex<u32>,
+    defaults: RoomDefaults, // 新建房间的默认参数
+    cast: CastConfig,
+    storage: Arc<Storage>,
+    shutting_down: AtomicBool, // 停机中不再新建房间
+}
+
+impl Lobby {
+    pub fn new(defaults: RoomDefaults, cast: CastConfig, storage: Arc<Storage>) -> Self {
+        Lobby {
+            rooms: Mutex::new(HashMap::new()),
+            next_room_id: Mutex::new(1),
+            defaults,
+            cast,
+            storage,
+            shutting_down: AtomicBool::new(false),
+        }
+    }
+
+    // 启动时恢复上次未结束的对局：先按停机快照，再按预写日志，日志里没有的再按数据库
+    // 快照被放弃时其中的对局与无法恢复的对局一样标记为中止
+    pub async fn restore(
+        &self,
+        snapshots: Vec<RoomSnapshot>,
+        use_snapshots: bool,
+    ) -> Result<(), String> {
+        let mut saved = vec![];
+        if let Some(config) = &self.storage.journal {
+            for (journal, game) in Journal::load_all(config)? {
+                saved.push((game, Some(journal)));
+            }
+        }
+        let journaled: HashSet<i64> = saved.iter().filter_map(|(game, _)| game.game_id).collect();
+        let mut unfinished = HashSet::new();
+        for game in self.storage.unfinished_games()? {
+            unfinished.extend(game.game_id);
+            if !game.game_id.is_some_and(|id| journaled.contains(&id)) {
+                saved.push((game, None));
+            }
+        }
+        // 数据库里已没有这局（例如数据库被清空），只按日志恢复，不再写数据库
+        for (game, _) in saved.iter_mut() {
+            if game.game_id.is_some_and(|id| !unfinished.contains(&id)) {
+                game.game_id = None;
+            }
+        }
+
+        let mut rooms = self.rooms.lock().await;
+        let mut next_room_id = self.next_room_id.lock().await;
+        for snapshot in snapshots {
+            // 快照里的对局同样有日志和数据库记录，取出日志以便继续追加
+            let position = saved
+                .iter()
+                .position(|(game, _)| game.room_id == snapshot.room_id);
+            let journal = position.and_then(|i| saved.remove(i).1);
+            let room_id = snapshot.room_id;
+
+            if !use_snapshots || rooms.contains_key(&room_id) {
+                info!(room_id, "snapshot discarded");
+                if let Some(journal) = journal {
+                    journal.remove();
+                }
+                if let Some(game_id) = snapshot.game_id {
+                    self.storage.abort_game(game_id);
+                }
+                continue;
+            }
+
+            let room = Arc::new(Room::from_snapshot(snapshot, self.storage.clone(), journal));
+            *next_room_id = (*next_room_id).max(room.id + 1);
+            room.start_timer();
+            // 停机前可能正轮到机器人
+            room.run_bots().await;
+            info!(room_id, "room restored from snapshot");
+            rooms.insert(room_id, room);
+        }
+        for (game, journal) in saved {
+            let game_id = game.game_id;
+            let room_id = game.room_id;
+            let journal_path = journal.as_ref().map(|j| j.path().to_path_buf());
+
+            let result = if rooms.contains_key(&room_id) {
+                // 同一房间号只可能有一局在进行，重复的说明记录有误
+                Err(format!("Room {} already restored", room_id))
+            } else {
+                Room::resume(game, self.storage.clone(), journal)
+            };
+
+            match result {
+                Ok(room) => {
+                    let room = Arc::new(room);
+                    *next_room_id = (*next_room_id).max(room.id + 1);
+                    room.start_timer();
+                    room.run_bots().await;
+                    info!(room_id = room.id, "room restored");
+                    rooms.insert(room.id, room);
+                }
+                Err(err) => {
+                    error!(room_id, error = %err, "cannot restore room");
+                    if let Some(path) = journal_path {
+                        Journal::discard(&path);
+                    }
+                    if let Some(game_id) = game_id {
+                        self.storage.abort_game(game_id);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn list(&self) -> Vec<RoomInfo> {
+        self.prune_finished().await;
+        let rooms: Vec<Arc<Room>> = self.rooms.lock().await.values().cloned().collect();
+
+        // 私密房间不出现在列表中
+        let mut infos = vec![];
+        for room in rooms.iter().filter(|room| !room.is_private()) {
+            infos.push(room.info().await);
+        }
+        infos.sort_by_key(|info| info.room_id);
+        infos
+    }
+
+    pub async fn create(
+        &self,
+        name: String,
+        seats: Option<usize>,
+        rules: Option<GameRules>,
+        private: bool,
+        password: Option<String>,
+    ) -> Result<Arc<Room>, String> {
+        if self.shutting_down.load(Ordering::Relaxed) {
+            return Err("Server is shutting down".to_string());
+        }
+        let seats = seats.unwrap_or(self.defaults.seats);
+        let rules = rules.unwrap_or(self.defaults.rules);
+
+        // 参数校验
+        let name = name.trim().to_string();
+        if name.is_empty() || name.chars().count() > 32 {
+            return Err("Room name must be 1..=32 characters".to_string());
+        }
+        if !(2..=MAX_SEATS).contains(&seats) {
+            return Err(format!(
+                "Invalid seats: {}, expected 2..={}",
+                seats, MAX_SEATS
+            ));
+        }
+        rules.validate()?;
+        if password
+            .as_ref()
+            .is_some_and(|p| p.is_empty() || p.len() > 64)
+        {
+            return Err("Password must be 1..=64 bytes".to_string());
+        }
+
+        let room_id = {
+            let mut next = self.next_room_id.lock().await;
+            let id = *next;
+            *next += 1;
+            id
+        };
+
+        let mut rooms = self.rooms.lock().await;
+
+        // 私密房间生成不重复的邀请码
+        let invite_code = private.then(|| {
+            loop {
+                let code = new_invite_code();
+                if !rooms
+                    .values()
+                    .any(|room| room.invite_code.as_deref() == Some(code.as_str()))
+                {
+                    break code;
+                }
+            }
+        });
+
+        let room = Arc::new(Room::new(
+            room_id,
+            name,
+            seats,
+            rules,
+            invite_code,
+            password,
+            self.storage.clone(),
+        ));
+        rooms.insert(room_id, room.clone());
+
+        // 等人超时后由机器人补位；房间已关闭则什么也不做
+        let weak = Arc::downgrade(&room);
+        let waiting_timeout = self.defaults.waiting_timeout;
+        tokio::spawn(async move {
+            tokio::time::sleep(waiting_timeout).await;
+            if let Some(room) = weak.upgrade() {
+                room.waiting_timeout().await;
+            }
+        });
+        info!(room_id, seats, private, "room created");
+        Ok(room)
+    }
+
+    // 快速加入：在还在等人、不要密码的公开房间里挑一个，没有就新建
+    // 开启按分匹配且已登录时挑平均分最接近的，超出范围的房间排在后面；否则挑空位最少的，早点开局
+    pub async fn quick_match(&self, account: Option<&Account>) -> Result<Arc<Room>, String> {
+        let rooms: Vec<Arc<Room>> = self.rooms.lock().await.values().cloned().collect();
+        let mut open = vec![];
+        for room in rooms.into_iter().filter(|room| !room.is_private()) {
+            let info = room.info().await;
+            if !info.started && !info.locked && info.players + info.bots < info.seats {
+                open.push((room, info));
+            }
+        }
+
+        let rating = &self.storage.rating;
+        let mine = match account {
+            Some(account) if rating.matchmaking => Some(account.rating.unwrap_or(rating.initial)),
+            _ => None,
+        };
+        let best = match mine {
+            Some(mine) => open.into_iter().min_by(|(_, a), (_, b)| {
+                let distance =
+                    |info: &RoomInfo| (info.rating.map_or(rating.initial, f64::from) - mine).abs();
+                let (a, b) = (distance(a), distance(b));
+                (a > rating.match_range)
+                    .cmp(&(b > rating.match_range))
+                    .then(a.total_cmp(&b))
+            }),
+            None => open
+                .into_iter()
+                .min_by_key(|(room, info)| (info.seats - info.players - info.bots, room.id)),
+        };
+
+        match best {
+            Some((room, _)) => Ok(room),
+            None => {
+                self.create("Quick match".to_string(), None, None, false, None)
+                    .await
+            }
+        }
+    }
+
+    // 所有房间（含私密房间），按房间号排序
+    pub async fn rooms(&self) -> Vec<Arc<Room>> {
+        self.prune_finished().await;
+        let mut rooms: Vec<Arc<Room>> = self.rooms.lock().await.values().cloned().collect();
+        rooms.sort_by_key(|room| room.id);
+        rooms
+    }
+
+    // 强制关闭房间
+    pub async fn abort(&self, room_id: u32, reason: &str) -> Result<(), String> {
+        let room = self.rooms.lock().await.remove(&room_id);
+        let Some(room) = room else {
+            return Err("No such room".to_string());
+        };
+
+        room.abort(reason).await;
+        info!(room_id, reason, "room aborted");
+        Ok(())
+    }
+
+    // 停机第一步：不再新建房间，通知所有在房间里的玩家
+    pub async fn begin_shutdown(&self, grace_secs: u64) {
+        self.shutting_down.store(true, Ordering::Relaxed);
+        for room in self.rooms().await {
+            room.broadcast_message(&NetMessage::ServerShutdown { grace_secs })
+                .await;
+        }
+    }
+
+    // 停机最后一步：冻结所有房间并断开连接，返回进行中对局的快照
+    pub async fn shut_down(&self) -> Vec<RoomSnapshot> {
+        let mut snapshots = vec![];
+        for room in self.rooms().await {
+            snapshots.extend(room.shut_down().await);
+        }
+        snapshots
+    }
+
+    // 进行中对局的快照，房间照常运行
+    pub async fn snapshot(&self) -> Vec<RoomSnapshot> {
+        let mut snapshots = vec![];
+        for room in self.rooms().await {
+            snapshots.extend(room.snapshot().await);
+        }
+        snapshots
+    }
+
+    // 向所有房间里的玩家和观众发送公告
+    pub async fn notice(&self, text: &str) {
+        for room in self.rooms().await {
+            room.broadcast_message(&NetMessage::Notice {
+                text: text.to_string(),
+            })
+            .await;
+        }
+    }
+
+    // 查询放到阻塞线程里；数据库出错时只记日志，回复给客户端的不带细节
+    pub async fn leaderboard(&self, limit: Option<usize>) -> Result<Vec<PlayerStats>, String> {
+        let limit = limit
+            .unwrap_or(LEADERBOARD_DEFAULT)
+            .clamp(1, LEADERBOARD_MAX);
+        let storage = self.storage.clone();
+        tokio::task::spawn_blocking(move || storage.leaderboard(limit))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|result| result)
+            .map_err(|err| {
+                error!(error = %err, "cannot read leaderboard");
+                "Leaderboard unavailable".to_string()
+            })
+    }
+
+    pub async fn player_stats(&self, username: String) -> Result<Option<PlayerStats>, String> {
+        let storage = self.storage.clone();
+        tokio::task::spawn_blocking(move || storage.player_stats(&username))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|result| result)
+            .map_err(|err| {
+                error!(error = %err, "cannot read player stats");
+                "Stats unavailable".to_string()
+            })
+    }
+
+    // 分数可能在别的房间里变过，入座前重新读取；查不到时沿用原来的
+    pub async fn refresh_rating(&self, account: &mut Account) {
+        let storage = self.storage.clone();
+        let id = account.id;
+        match tokio::task::spawn_blocking(move || storage.rating(id))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|result| result)
+        {
+            Ok(rating) => account.rating = rating,
+            Err(err) => error!(error = %err, "cannot read rating"),
+        }
+    }
+
+    // 账号操作要算密码哈希，放到阻塞线程里，不占用异步工作线程
+    pub async fn register(&self, username: String, password: String) -> Result<Account, String> {
+        let storage = self.storage.clone();
+        tokio::task::spawn_blocking(move || accounts::register(&storage, &username, &password))
+            .await
+            .map_err(|e| e.to_string())?
+    }
+
+    pub async fn login(&self, username: String, password: String) -> Result<Account, String> {
+        let storage = self.storage.clone();
+        tokio::task::spawn_blocking(move || accounts::login(&storage, &username, &password))
+            .await
+            .map_err(|e| e.to_string())?
+    }
+
+    // 解说视角的密钥校验，返回延迟轮数
+    pub fn authorize_cast(&self, key: &str, delay_rounds: Option<u8>) -> Result<u8, String> {
+        self.cast.authorize(key, delay_rounds)
+    }
+
+    pub async fn get(&self, room_id: u32) -> Option<Arc<Room>> {
+        self.rooms.lock().await.get(&room_id).cloned()
+    }
+
+    // 按房间号查找公开房间，私密房间只能凭邀请码找到
+    pub async fn get_public(&self, room_id: u32) -> Option<Arc<Room>> {
+        self.get(room_id).await.filter(|room| !room.is_private())
+    }
+
+    // 按邀请码查找私密房间（不区分大小写）
+    pub async fn find_invite(&self, code: &str) -> Option<Arc<Room>> {
+        let code = code.trim().to_ascii_uppercase();
+        self.rooms
+            .lock()
+            .await
+            .values()
+            .find(|room| room.invite_code.as_deref() == Some(code.as_str()))
+            .cloned()
+    }
+
+    // 按会话令牌查找所在房间
+    pub async fn find_session(&self, token: &str) -> Option<Arc<Room>> {
+        let rooms: Vec<Arc<Room>> = self.rooms.lock().await.values().cloned().collect();
+
+        for room in rooms {
+            if room.seating.lock().await.sessions.contains_key(token) {
+                return Some(room);
+            }
+        }
+        None
+    }
+
+    // 房间无人入座时回收
+    // 对局结束时已没有人在线的房间不会再有连接来触发 remove_if_empty，在这里清掉
+    async fn prune_finished(&self) {
+        let rooms: Vec<Arc<Room>> = self.rooms.lock().await.values().cloned().collect();
+        for room in rooms {
+            if matches!(*room.phase.lock().await, ServerPhase::Finished) {
+                self.remove_if_empty(room.id).await;
+            }
+        }
+    }
+
+    pub async fn remove_if_empty(&self, room_id: u32) {
+        let mut rooms = self.rooms.lock().await;
+        let Some(room) = rooms.get(&room_id).cloned() else {
+            return;
+        };
+
+        if room.is_empty().await {
+            rooms.remove(&room_id);
+            room.close_spectators("All players left").await;
+            info!(room_id, "room closed");
+        }
+    }
+}
+
+// 6 位邀请码，去掉了容易混淆的 0/O、1/I/L
+fn new_invite_code() -> String {
+    const CHARSET: &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ23456789";
+    let mut rng = rand::rng();
+    (0..6)
+        .map(|_| *CHARSET.choose(&mut rng).unwrap() as char)
+        .collect()
+}
@@ -1,226 +1,747 @@
+mod accounts;
+mod admin;
+mod cast;
+mod config;
+mod console;
+mod heartbeat;
+mod inbound;
+mod journal;
+mod lobby;
+mod metrics;
+mod outbox;
+mod ratelimit;
+mod rating;
+mod room;
+mod snapshot;
+mod stats;
+mod storage;
+mod transport;
+
+use accounts::Account;
+use clap::Parser;
+use config::{Args, Config};
 use game_core::*;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use heartbeat::{Heartbeat, HeartbeatConfig};
+use inbound::{Inbound, Received};
+use lobby::Lobby;
+use metrics::METRICS;
+use outbox::Outbox;
+use ratelimit::Gatekeeper;
+use room::{Room, next_conn_id};
+use snapshot::RestorePolicy;
+use std::io::{IsTerminal, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use tokio::net::tcp::OwnedWriteHalf;
-use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    net::{TcpListener, TcpStream},
-    sync::Mutex,
-};
-
-#[derive(Serialize, Deserialize)]
-enum NetMessage {
-    Command(Command),
-    Event(Event),
-}
-
-enum ServerPhase {
-    Waiting,  // 等人
-    Playing,  // 游戏中
-    Finished, // 已结束，等待是否重开
-}
+use std::time::Duration;
+use storage::Storage;
+use tokio::net::TcpListener;
+use tracing::{Instrument, Span, debug, error, field, info, info_span, warn};
+use transport::{LineReader, LineWriter};
 
 #[tokio::main]
 async fn main() {
-    let phase = Arc::new(Mutex::new(ServerPhase::Waiting));
-    let next_player_id = Arc::new(AtomicUsize::new(0));
-    let listener = TcpListener::bind("0.0.0.0:9000").await.unwrap();
-    println!("Server listening on 9000");
+    // 配置有误时直接退出，不带着半对的配置启动
+    let config = match Config::load(Args::parse()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    };
+    logging::init(config.log_level, config.log_format);
+    if let Err(err) = std::fs::create_dir_all(&config.data_dir) {
+        error!(path = %config.data_dir.display(), error = %err, "cannot create data directory");
+        std::process::exit(1);
+    }
 
-    let game = Arc::new(Mutex::new(init_game()));
-    let clients: Arc<Mutex<HashMap<usize, OwnedWriteHalf>>> = Arc::new(Mutex::new(HashMap::new())); // 修改为 HashMap 存储 player_id 和 TcpStream 的映射
+    let listener = bind(config.listen_addr().unwrap()).await;
+    info!(addr = %listener.local_addr().unwrap(), "server listening");
+    let ws_listener = match config.websocket_addr().unwrap() {
+        Some(addr) => {
+            let listener = bind(addr).await;
+            info!(addr = %listener.local_addr().unwrap(), "WebSocket listening");
+            Some(listener)
+        }
+        None => None,
+    };
+    let admin_listener = match config.admin_addr().unwrap() {
+        Some(addr) => {
+            let listener = bind(addr).await;
+            info!(addr = %listener.local_addr().unwrap(), "admin API listening");
+            Some(listener)
+        }
+        None => None,
+    };
 
-    loop {
-        let (socket, _) = listener.accept().await.unwrap();
-
-        let mut phase_guard = phase.lock().await;
-        if !matches!(*phase_guard, ServerPhase::Waiting) {
-            // 游戏中 / 已结束，不接新玩家
-            let (_r, mut w) = socket.into_split();
-            let _ = w.write_all(b"Game already started\n").await;
-            continue;
+    let storage = match Storage::open(
+        &config.data_dir.join("games.db"),
+        config.journal(),
+        config.rating.clone(),
+    ) {
+        Ok(storage) => Arc::new(storage),
+        Err(err) => {
+            error!(error = %err, "cannot open storage");
+            std::process::exit(1);
         }
+    };
+    let lobby = Arc::new(Lobby::new(
+        config.room_defaults().unwrap(),
+        config.cast.clone(),
+        storage.clone(),
+    ));
+    let snapshot_path = config.snapshot_path();
+    let snapshots = snapshot::load(&snapshot_path).unwrap_or_else(|err| {
+        // 快照坏了还有日志和数据库可以恢复
+        error!(error = %err, "cannot load snapshot");
+        snapshot::discard(&snapshot_path);
+        vec![]
+    });
+    let use_snapshots =
+        !snapshots.is_empty() && confirm_restore(config.shutdown.restore, snapshots.len());
+    if let Err(err) = lobby.restore(snapshots, use_snapshots).await {
+        error!(error = %err, "cannot restore games");
+        std::process::exit(1);
+    }
+    if let Err(err) = snapshot::remove(&snapshot_path) {
+        error!(error = %err, "cannot remove snapshot");
+    }
+    let heartbeat = config.heartbeat();
+    let gatekeeper = Arc::new(Gatekeeper::new(config.limits.clone()));
 
-        let player_id = next_player_id.fetch_add(1, Ordering::SeqCst);
+    if let Some(listener) = admin_listener {
+        tokio::spawn(admin::serve(listener, lobby.clone()));
+    }
+    // 后台运行或输入被重定向时不开
+    if config.console && std::io::stdin().is_terminal() {
+        tokio::spawn(console::run(lobby.clone(), config.data_dir.clone()));
+    }
+    let ws_task = ws_listener.map(|listener| {
+        tokio::spawn(accept_websocket(
+            listener,
+            lobby.clone(),
+            heartbeat,
+            gatekeeper.clone(),
+        ))
+    });
+
+    tokio::select! {
+        _ = accept_tcp(listener, lobby.clone(), heartbeat, gatekeeper) => {}
+        _ = shutdown_signal() => {}
+    }
+    // 停止接受新连接
+    if let Some(task) = ws_task {
+        task.abort();
+    }
+    shut_down(&lobby, config.shutdown.grace_secs, &snapshot_path).await;
+    // 退出前等写线程把排队的对局记录写完
+    storage.flush().await;
+    // 控制台读标准输入的阻塞线程不会自行结束，直接退出而不是等运行时收尾
+    std::process::exit(0);
+}
 
-        if player_id >= 5 {
-            let (_r, mut w) = socket.into_split();
-            let _ = w.write_all(b"Player limit reached\n").await;
-            continue;
+// 询问是否恢复上次停机时保存的房间；不在终端上运行时直接恢复
+fn confirm_restore(policy: RestorePolicy, rooms: usize) -> bool {
+    match policy {
+        RestorePolicy::Always => true,
+        RestorePolicy::Never => false,
+        RestorePolicy::Ask => {
+            if !std::io::stdin().is_terminal() {
+                return true;
+            }
+            print!(
+                "Restore {} room(s) saved at the last shutdown? [Y/n] ",
+                rooms
+            );
+            let _ = std::io::stdout().flush();
+
+            let mut answer = String::new();
+            if std::io::stdin().read_line(&mut answer).is_err() {
+                return true;
+            }
+            !matches!(answer.trim().to_ascii_lowercase().as_str(), "n" | "no")
         }
+    }
+}
 
-        println!("Client connected with player_id {}", player_id);
+// SIGINT 或 SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut terminate = signal(SignalKind::terminate()).expect("cannot listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+// 通知玩家并等待宽限期（再次收到信号则立即停机），然后保存进行中的房间
+async fn shut_down(lobby: &Lobby, grace_secs: u64, snapshot_path: &Path) {
+    info!(grace_secs, "shutting down");
+    lobby.begin_shutdown(grace_secs).await;
+    tokio::select! {
+        _ = tokio::time::sleep(Duration::from_secs(grace_secs)) => {}
+        _ = shutdown_signal() => info!("grace period skipped"),
+    }
+
+    let snapshots = lobby.shut_down().await;
+    if snapshots.is_empty() {
+        return;
+    }
+    match snapshot::save(snapshot_path, &snapshots) {
+        Ok(()) => info!(rooms = snapshots.len(), path = %snapshot_path.display(), "rooms saved"),
+        Err(err) => error!(error = %err, "cannot save rooms"),
+    }
+}
+
+async fn accept_tcp(
+    listener: TcpListener,
+    lobby: Arc<Lobby>,
+    heartbeat: HeartbeatConfig,
+    gatekeeper: Arc<Gatekeeper>,
+) {
+    loop {
+        let (socket, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                warn!(error = %err, "accept failed");
+                continue;
+            }
+        };
 
         tokio::spawn({
-            let game = game.clone();
-            let clients = clients.clone();
+            let lobby = lobby.clone();
+            let gatekeeper = gatekeeper.clone();
 
             async move {
-                handle_client(socket, player_id, game, clients).await;
+                let max_bytes = gatekeeper.config().max_line_bytes;
+                let (reader, writer) = transport::tcp(socket, max_bytes);
+                handle_connection(reader, writer, peer.ip(), lobby, heartbeat, gatekeeper).await;
             }
+            .instrument(connection_span(peer, "tcp"))
         });
+    }
+}
 
-        // 如果正好 5 人，立刻发牌
-        if player_id == 4 && game.lock().await.is_card == false {
-            *phase_guard = ServerPhase::Playing;
-            start_game(game.clone(), clients.clone()).await;
-            game.lock().await.is_card = true;
+async fn bind(addr: SocketAddr) -> TcpListener {
+    match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!(%addr, error = %err, "cannot listen");
+            std::process::exit(1);
         }
     }
 }
 
-async fn handle_client(
-    socket: TcpStream,
-    player_id: usize,
-    game: Arc<Mutex<GameState>>,
-    clients: Arc<Mutex<HashMap<usize, OwnedWriteHalf>>>, // 修改为 HashMap
+// WebSocket 连接握手后与 TCP 连接走同一套处理，共用大厅和房间
+async fn accept_websocket(
+    listener: TcpListener,
+    lobby: Arc<Lobby>,
+    heartbeat: HeartbeatConfig,
+    gatekeeper: Arc<Gatekeeper>,
 ) {
-    let (r, mut w) = socket.into_split();
-    let mut reader = BufReader::new(r).lines();
+    loop {
+        let (socket, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                warn!(error = %err, "accept failed");
+                continue;
+            }
+        };
 
-    // 将客户端添加到 clients 列表
-    clients.lock().await.insert(player_id, w);
+        tokio::spawn({
+            let lobby = lobby.clone();
+            let gatekeeper = gatekeeper.clone();
 
-    // 发放 player_id
-    {
-        let assign = NetMessage::Event(Event::PlayerAssigned { player_id });
-        let text = serde_json::to_string(&assign).unwrap() + "\n";
+            async move {
+                let max_bytes = gatekeeper.config().max_line_bytes;
+                match transport::websocket(socket, max_bytes).await {
+                    Ok((reader, writer)) => {
+                        handle_connection(reader, writer, peer.ip(), lobby, heartbeat, gatekeeper)
+                            .await;
+                    }
+                    Err(err) => info!(error = %err, "connection rejected"),
+                }
+            }
+            .instrument(connection_span(peer, "websocket"))
+        });
+    }
+}
+
+// 每条连接一个 span，conn_id 在分配后补上，account 在登录后补上
+fn connection_span(peer: SocketAddr, transport: &'static str) -> Span {
+    info_span!(
+        "conn",
+        conn_id = field::Empty,
+        %peer,
+        transport,
+        account = field::Empty
+    )
+}
 
-        // 需要重新借用写半部
-        if let Some(writer) = clients.lock().await.get_mut(&player_id) {
-            let _ = writer.write_all(text.as_bytes()).await;
+/* ================= 大厅：建房 / 入座 / 断线重连 ================= */
+async fn handle_connection(
+    reader: LineReader,
+    writer: LineWriter,
+    ip: IpAddr,
+    lobby: Arc<Lobby>,
+    heartbeat: HeartbeatConfig,
+    gatekeeper: Arc<Gatekeeper>,
+) {
+    let _connection = METRICS.connection_opened();
+    let outbox = Outbox::spawn(writer);
+    let conn_id = next_conn_id();
+    Span::current().record("conn_id", conn_id);
+
+    // 先回复原因再断开，客户端能看到为什么连不上
+    let slot = match gatekeeper.admit(ip) {
+        Ok(slot) => slot,
+        Err(err) => {
+            reject(&outbox, &err);
+            outbox.finish();
+            return;
         }
-    }
+    };
+    let mut inbound = Inbound::new(reader, Heartbeat::new(heartbeat), gatekeeper.config(), slot);
+    debug!("connection opened");
+    // 未登录即为游客
+    let mut account: Option<Account> = None;
 
-    while let Ok(Some(line)) = reader.next_line().await {
-        let msg: NetMessage = serde_json::from_str(&line).unwrap();
-
-        if let NetMessage::Command(cmd) = msg {
-            let mut game = game.lock().await;
-            match game.apply(cmd) {
-                Ok(events) => {
-                    for event in events {
-                        match &event {
-                            //
-                            Event::PredictionAccepted { .. }
-                            | Event::CardPlayed { .. }
-                            | Event::PosteriorPredictionAccepted { .. }
-                            | Event::RoundResult { .. } => {
-                                broadcast(&clients, &event).await;
-                            }
-                            Event::CardsDealt { player_id, .. } => {
-                                send_to_player(&clients, *player_id, &event).await;
-                            }
-                            _ => {}
+    loop {
+        let msg = match inbound.next(&outbox).await {
+            Received::Message(msg) => msg,
+            Received::Idle | Received::Active => continue,
+            Received::Closed => return,
+        };
+
+        let room = match msg {
+            NetMessage::Ping { nonce } => {
+                outbox.send(&NetMessage::Pong { nonce });
+                continue;
+            }
+            NetMessage::Pong { .. } => continue,
+            NetMessage::Register { username, password } => {
+                match lobby.register(username, password).await {
+                    Ok(registered) => {
+                        info!(account = %registered.username, "account registered");
+                        logged_in(&outbox, &mut account, registered);
+                    }
+                    Err(err) => reject(&outbox, &err),
+                }
+                continue;
+            }
+            NetMessage::Login { username, password } => {
+                match lobby.login(username, password).await {
+                    Ok(found) => logged_in(&outbox, &mut account, found),
+                    // 猜密码算违规，次数多了会被临时封禁
+                    Err(err) => {
+                        if inbound.violation(&outbox, &err) {
+                            return;
                         }
                     }
                 }
+                continue;
+            }
+            NetMessage::Leaderboard { limit } => {
+                match lobby.leaderboard(limit).await {
+                    Ok(players) => {
+                        outbox.send(&NetMessage::LeaderboardList { players });
+                    }
+                    Err(err) => reject(&outbox, &err),
+                }
+                continue;
+            }
+            NetMessage::GetStats { username } => {
+                match lobby.player_stats(username.clone()).await {
+                    Ok(stats) => {
+                        outbox.send(&NetMessage::Stats { username, stats });
+                    }
+                    Err(err) => reject(&outbox, &err),
+                }
+                continue;
+            }
+            NetMessage::ListRooms => {
+                let rooms = lobby.list().await;
+                outbox.send(&NetMessage::RoomList { rooms });
+                continue;
+            }
+            NetMessage::CreateRoom {
+                name,
+                seats,
+                rules,
+                private,
+                password,
+            } => match lobby.create(name, seats, rules, private, password).await {
+                Ok(room) => room,
                 Err(err) => {
-                    if let Some(writer) = clients.lock().await.get_mut(&player_id) {
-                        let _ = writer.write_all(format!("ERR {}\n", err).as_bytes()).await;
+                    reject(&outbox, &err);
+                    continue;
+                }
+            },
+            NetMessage::QuickMatch => {
+                if let Some(account) = &mut account {
+                    lobby.refresh_rating(account).await;
+                }
+                match lobby.quick_match(account.as_ref()).await {
+                    Ok(room) => room,
+                    Err(err) => {
+                        reject(&outbox, &err);
+                        continue;
                     }
                 }
             }
-        }
-    }
-}
+            // 猜房间密码和邀请码也算违规，与猜登录密码一样
+            NetMessage::JoinRoom { room_id, password } => {
+                let Some(room) = lobby.get_public(room_id).await else {
+                    reject(&outbox, "No such room");
+                    continue;
+                };
+                if let Err(err) = room.check_password(password.as_deref()) {
+                    if inbound.violation(&outbox, &err) {
+                        return;
+                    }
+                    continue;
+                }
+                room
+            }
+            NetMessage::JoinByCode { code, password } => {
+                let Some(room) = lobby.find_invite(&code).await else {
+                    if inbound.violation(&outbox, "Invalid invite code") {
+                        return;
+                    }
+                    continue;
+                };
+                if let Err(err) = room.check_password(password.as_deref()) {
+                    if inbound.violation(&outbox, &err) {
+                        return;
+                    }
+                    continue;
+                }
+                room
+            }
+            NetMessage::Spectate { room_id, password } => {
+                let Some(room) = lobby.get_public(room_id).await else {
+                    reject(&outbox, "No such room");
+                    continue;
+                };
+                if !spectate(&mut inbound, &outbox, conn_id, &room, password).await {
+                    return;
+                }
+                continue;
+            }
+            NetMessage::SpectateByCode { code, password } => {
+                let Some(room) = lobby.find_invite(&code).await else {
+                    if inbound.violation(&outbox, "Invalid invite code") {
+                        return;
+                    }
+                    continue;
+                };
+                if !spectate(&mut inbound, &outbox, conn_id, &room, password).await {
+                    return;
+                }
+                continue;
+            }
+            NetMessage::Cast {
+                room_id,
+                key,
+                delay_rounds,
+            } => {
+                // 私密房间也可以按房间号观看，密钥即授权
+                let Some(room) = lobby.get(room_id).await else {
+                    reject(&outbox, "No such room");
+                    continue;
+                };
+                let delay_rounds = match lobby.authorize_cast(&key, delay_rounds) {
+                    Ok(delay_rounds) => delay_rounds,
+                    Err(err) => {
+                        warn!(room_id, error = %err, "cast refused");
+                        reject(&outbox, &err);
+                        continue;
+                    }
+                };
+                if let Err(err) = room.add_caster(outbox.clone(), conn_id, delay_rounds).await {
+                    reject(&outbox, &err);
+                    continue;
+                }
+                info!(room_id, delay_rounds, "casting");
 
-async fn send_to_player(
-    clients: &Arc<Mutex<HashMap<usize, OwnedWriteHalf>>>,
-    player_id: usize,
-    msg: &game_core::Event,
-) {
-    let text = serde_json::to_string(msg).unwrap() + "\n";
+                let left = handle_spectator(&mut inbound, &outbox, conn_id, &room)
+                    .instrument(info_span!("cast", room_id))
+                    .await;
+                if !left {
+                    return;
+                }
+                continue;
+            }
+            NetMessage::Reconnect { token } => {
+                let Some(room) = lobby.find_session(&token).await else {
+                    reject(&outbox, "Invalid session token");
+                    continue;
+                };
+                let Some(player_id) = room.reconnect(&token, outbox.clone(), conn_id).await else {
+                    // 令牌在查找后恰好失效
+                    reject(&outbox, "Invalid session token");
+                    continue;
+                };
+
+                info!(room_id = room.id, player_id, "reconnected");
+
+                let left = handle_client(&mut inbound, &outbox, &token, conn_id, &room)
+                    .instrument(room_span(&room))
+                    .await;
+                lobby.remove_if_empty(room.id).await;
+                if !left {
+                    return;
+                }
+                continue;
+            }
+            _ => {
+                reject(&outbox, "Not in a room");
+                continue;
+            }
+        };
 
-    // 只在这里短暂加锁
-    let mut client = {
-        let mut guard = clients.lock().await;
-        guard.remove(&player_id)
-    };
+        // 入座
+        if let Some(account) = &mut account {
+            lobby.refresh_rating(account).await;
+        }
+        let (player_id, token) = match room.join(outbox.clone(), conn_id, account.clone()).await {
+            Ok(joined) => joined,
+            Err(err) => {
+                reject(&outbox, &err);
+                lobby.remove_if_empty(room.id).await;
+                continue;
+            }
+        };
 
-    if let Some(mut writer) = client {
-        let _ = writer.write_all(text.as_bytes()).await;
+        info!(room_id = room.id, player_id, "joined room");
 
-        // 写完再放回去
-        clients.lock().await.insert(player_id, writer);
+        // 主动离座则回到大厅，否则连接已断开
+        let left = handle_client(&mut inbound, &outbox, &token, conn_id, &room)
+            .instrument(room_span(&room))
+            .await;
+        lobby.remove_if_empty(room.id).await;
+        if !left {
+            return;
+        }
     }
 }
 
-async fn broadcast(clients: &Arc<Mutex<HashMap<usize, OwnedWriteHalf>>>, msg: &game_core::Event) {
-    let text = serde_json::to_string(msg).unwrap() + "\n";
-
-    // 先把所有 writer 拿出来
-    let mut writers: Vec<(usize, OwnedWriteHalf)> = {
-        let mut guard = clients.lock().await;
-        guard.drain().collect()
-    };
-
-    // 逐个写（无锁）
-    for (_, writer) in writers.iter_mut() {
-        let _ = writer.write_all(text.as_bytes()).await;
-    }
-
-    // 再全部放回
-    let mut guard = clients.lock().await;
-    for (pid, writer) in writers {
-        guard.insert(pid, writer);
+fn error(message: &str) -> NetMessage {
+    NetMessage::Error {
+        message: message.to_string(),
     }
 }
 
-/* ===== 初始化 GameState（5人） ===== */
+fn logged_in(outbox: &Outbox, account: &mut Option<Account>, logged_in: Account) {
+    Span::current().record("account", logged_in.username.as_str());
+    info!("logged in");
+    outbox.send(&NetMessage::LoggedIn {
+        username: logged_in.username.clone(),
+    });
+    *account = Some(logged_in);
+}
 
-fn init_game() -> GameState {
-    let players = (0..5)
-        .map(|id| PlayerState {
-            id,
-            is_first: false,
-            hand: vec![],
-            score: 0,
-            prediction: None,
-            posterior_prediction: None,
-            has_predicted: false,
-            has_played: false,
-        })
-        .collect();
+// 拒绝客户端的请求：记日志并回复错误
+fn reject(outbox: &Outbox, message: &str) {
+    info!(error = message, "request rejected");
+    outbox.send(&error(message));
+}
 
-    GameState::new(players)
+// 在房间内的连接；换座后 player_id 随之更新
+fn room_span(room: &Room) -> Span {
+    info_span!("room", room_id = room.id, player_id = field::Empty)
 }
 
-/* ================= 发牌阶段 ================= */
-async fn start_game(
-    game: Arc<Mutex<GameState>>,
-    clients: Arc<Mutex<HashMap<usize, OwnedWriteHalf>>>,
-) {
-    let events = {
-        let mut game = game.lock().await;
-        game.deal_cards()
-    };
+/* ================= 观众 ================= */
+
+// 校验密码后进入房间观看；回到大厅时返回 true，连接断开（包括猜密码被封禁）时返回 false
+async fn spectate(
+    inbound: &mut Inbound,
+    outbox: &Outbox,
+    conn_id: u64,
+    room: &Arc<Room>,
+    password: Option<String>,
+) -> bool {
+    if let Err(err) = room.check_password(password.as_deref()) {
+        return !inbound.violation(outbox, &err);
+    }
+    if let Err(err) = room.add_spectator(outbox.clone(), conn_id).await {
+        reject(outbox, &err);
+        return true;
+    }
+    info!(room_id = room.id, "spectating");
+
+    handle_spectator(inbound, outbox, conn_id, room)
+        .instrument(info_span!("spectate", room_id = room.id))
+        .await
+}
 
-    for event in events {
-        if let Event::CardsDealt { player_id, .. } = &event {
-            send_to_player(&clients, *player_id, &event).await;
+// 观众只能应答心跳和离开
+async fn handle_spectator(
+    inbound: &mut Inbound,
+    outbox: &Outbox,
+    conn_id: u64,
+    room: &Room,
+) -> bool {
+    loop {
+        let msg = match inbound.next(outbox).await {
+            Received::Message(msg) => msg,
+            Received::Idle | Received::Active => continue,
+            Received::Closed => break,
+        };
+
+        match msg {
+            NetMessage::Ping { nonce } => {
+                outbox.send(&NetMessage::Pong { nonce });
+            }
+            NetMessage::Pong { .. } => {}
+            NetMessage::LeaveRoom => {
+                room.remove_spectator(conn_id).await;
+                info!("stopped spectating");
+                outbox.send(&NetMessage::RoomLeft);
+                return true;
+            }
+            _ => reject(outbox, "Spectators cannot act"),
         }
     }
 
-    broadcast(&clients, &Event::GameStarted).await;
+    room.remove_spectator(conn_id).await;
+    false
 }
 
-/* ================= 预测阶段 ================= */
+// 处理房间内的消息；主动离座时返回 true，连接断开时返回 false
+async fn handle_client(
+    inbound: &mut Inbound,
+    outbox: &Outbox,
+    token: &str,
+    conn_id: u64,
+    room: &Arc<Room>,
+) -> bool {
+    loop {
+        let received = inbound.next(outbox).await;
+        if let Received::Closed = received {
+            break;
+        }
 
-/* ================= 出牌阶段 ================= */
+        // 换座后 player_id 会变，每条消息都按令牌重新查一次
+        let Some(player_id) = room.player_of(token).await else {
+            break;
+        };
+        Span::current().record("player_id", player_id);
+
+        let msg = match received {
+            Received::Message(msg) => msg,
+            Received::Idle => {
+                room.set_idle(player_id, true).await;
+                continue;
+            }
+            Received::Active => {
+                room.set_idle(player_id, false).await;
+                continue;
+            }
+            Received::Closed => break,
+        };
 
-/* ================= 后验预测阶段 ================= */
+        match msg {
+            NetMessage::Ping { nonce } => {
+                outbox.send(&NetMessage::Pong { nonce });
+            }
+            NetMessage::Pong { .. } => {}
+            NetMessage::Command(cmd) => {
+                // 只能以自己的座位行动
+                if cmd.player_id() != player_id {
+                    info!(?cmd, error = "Not your seat", "command rejected");
+                    METRICS.command_rejected("Not your seat");
+                    outbox.send(&error("Not your seat"));
+                    continue;
+                }
 
-/* ================= 重开投票阶段 ================= */
-async fn reset_game(
-    game: &Arc<Mutex<GameState>>,
-    clients: &Arc<Mutex<HashMap<usize, OwnedWriteHalf>>>,
-    phase: &Arc<Mutex<ServerPhase>>,
-) {
-    *game.lock().await = init_game();
-    *phase.lock().await = ServerPhase::Waiting;
+                match room.apply(cmd.clone()).await {
+                    Ok(()) => {
+                        debug!(?cmd, "command accepted");
+                        METRICS.command_accepted();
+                        // 投票中止后房间已清空，不必等客户端断开
+                        if room.is_empty().await {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        info!(?cmd, error = %err, "command rejected");
+                        METRICS.command_rejected(&err);
+                        outbox.send(&error(&err));
+                    }
+                }
+            }
+            NetMessage::SetReady { ready } => {
+                if let Err(err) = room.set_ready(player_id, ready).await {
+                    reject(outbox, &err);
+                }
+            }
+            NetMessage::SwapSeat { seat } => {
+                if let Err(err) = room.swap_seat(player_id, seat).await {
+                    reject(outbox, &err);
+                }
+            }
+            NetMessage::AddBots => {
+                if let Err(err) = room.add_bots(player_id).await {
+                    reject(outbox, &err);
+                }
+            }
+            NetMessage::RemoveBot { seat } => {
+                if let Err(err) = room.remove_bot(player_id, seat).await {
+                    reject(outbox, &err);
+                }
+            }
+            NetMessage::StartGame => {
+                if let Err(err) = room.start(player_id).await {
+                    reject(outbox, &err);
+                }
+            }
+            NetMessage::Chat { text } => {
+                if !inbound.allow_chat() {
+                    reject(outbox, "Sending messages too fast");
+                    continue;
+                }
+                if let Err(err) = room.chat(player_id, &text).await {
+                    reject(outbox, &err);
+                }
+            }
+            NetMessage::Emote { emote } => {
+                if !inbound.allow_chat() {
+                    reject(outbox, "Sending messages too fast");
+                    continue;
+                }
+                if let Err(err) = room.emote(player_id, emote).await {
+                    reject(outbox, &err);
+                }
+            }
+            NetMessage::LeaveRoom => match room.leave(player_id).await {
+                Ok(()) => {
+                    info!("left room");
+                    outbox.send(&NetMessage::RoomLeft);
+                    return true;
+                }
+                Err(err) => {
+                    reject(outbox, &err);
+                }
+            },
+            _ => {
+                reject(outbox, "Already in a room");
+            }
+        }
+    }
 
-    broadcast(clients, &Event::PhaseChanged).await;
+    // 连接断开：游戏中座位保留给会话令牌
+    if let Some(player_id) = room.disconnect(token, conn_id).await {
+        info!(player_id, "disconnected");
+    }
+    false
 }
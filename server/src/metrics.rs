@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::Duration;
+
+// 进程内计数器，由管理接口的 /metrics 以 Prometheus 文本格式导出
+pub static METRICS: Metrics = Metrics::new();
+
+pub struct Metrics {
+    connections_total: AtomicU64,
+    connections_open: AtomicI64,
+    games_started: AtomicU64,
+    games_finished: AtomicU64,
+    games_aborted: AtomicU64,
+    commands_accepted: AtomicU64,
+    commands_rejected: Mutex<BTreeMap<&'static str, u64>>, // 按错误类别
+    round_duration: Summary,
+    broadcast_latency: Summary,
+}
+
+// 只记总和与次数，平均值由 Prometheus 端相除
+struct Summary {
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Summary {
+    const fn new() -> Self {
+        Summary {
+            sum_micros: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, duration: Duration) {
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+// 连接存活期间计入在线连接数
+pub struct ConnectionGuard(());
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        METRICS.connections_open.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Metrics {
+            connections_total: AtomicU64::new(0),
+            connections_open: AtomicI64::new(0),
+            games_started: AtomicU64::new(0),
+            games_finished: AtomicU64::new(0),
+            games_aborted: AtomicU64::new(0),
+            commands_accepted: AtomicU64::new(0),
+            commands_rejected: Mutex::new(BTreeMap::new()),
+            round_duration: Summary::new(),
+            broadcast_latency: Summary::new(),
+        }
+    }
+
+    pub fn connection_opened(&self) -> ConnectionGuard {
+        self.connections_total.fetch_add(1, Ordering::Relaxed);
+        self.connections_open.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard(())
+    }
+
+    pub fn game_started(&self) {
+        self.games_started.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn game_finished(&self) {
+        self.games_finished.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn game_aborted(&self) {
+        self.games_aborted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn command_accepted(&self) {
+        self.commands_accepted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn command_rejected(&self, err: &str) {
+        *self
+            .commands_rejected
+            .lock()
+            .unwrap()
+            .entry(error_kind(err))
+            .or_default() += 1;
+    }
+
+    pub fn round_finished(&self, duration: Duration) {
+        self.round_duration.observe(duration);
+    }
+
+    pub fn broadcast_sent(&self, latency: Duration) {
+        self.broadcast_latency.observe(latency);
+    }
+
+    // Prometheus 文本格式；房间数由调用方在导出时统计
+    pub fn render(&self, rooms_active: usize) -> String {
+        let mut out = String::new();
+
+        counter(
+            &mut out,
+            "connections_total",
+            "Connections accepted",
+            self.connections_total.load(Ordering::Relaxed),
+        );
+        gauge(
+            &mut out,
+            "connections_open",
+            "Connections currently open",
+            self.connections_open.load(Ordering::Relaxed),
+        );
+        gauge(
+            &mut out,
+            "rooms_active",
+            "Rooms currently open",
+            rooms_active as i64,
+        );
+        counter(
+            &mut out,
+            "games_started_total",
+            "Games started",
+            self.games_started.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "games_finished_total",
+            "Games played to the end",
+            self.games_finished.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "games_aborted_total",
+            "Games aborted before the end",
+            self.games_aborted.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "commands_accepted_total",
+            "Player commands accepted",
+            self.commands_accepted.load(Ordering::Relaxed),
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP game_commands_rejected_total Player commands rejected"
+        );
+        let _ = writeln!(out, "# TYPE game_commands_rejected_total counter");
+        for (kind, count) in self.commands_rejected.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "game_commands_rejected_total{{kind=\"{}\"}} {}",
+                kind, count
+            );
+        }
+
+        summary(
+            &mut out,
+            "round_duration_seconds",
+            "Time from the start of a round to its result",
+            &self.round_duration,
+        );
+        summary(
+            &mut out,
+            "broadcast_latency_seconds",
+            "Time to enqueue a broadcast to every client in a room",
+            &self.broadcast_latency,
+        );
+        out
+    }
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP game_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE game_{} counter", name);
+    let _ = writeln!(out, "game_{} {}", name, value);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: i64) {
+    let _ = writeln!(out, "# HELP game_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE game_{} gauge", name);
+    let _ = writeln!(out, "game_{} {}", name, value);
+}
+
+fn summary(out: &mut String, name: &str, help: &str, summary: &Summary) {
+    let sum = summary.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+    let _ = writeln!(out, "# HELP game_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE game_{} summary", name);
+    let _ = writeln!(out, "game_{}_sum {}", name, sum);
+    let _ = writeln!(
+        out,
+        "game_{}_count {}",
+        name,
+        summary.count.load(Ordering::Relaxed)
+    );
+}
+
+// 错误信息带有玩家编号等变量，归成固定的几类作为标签
+fn error_kind(err: &str) -> &'static str {
+    if err.starts_with("Not your turn") {
+        "not_your_turn"
+    } else if err.starts_with("Invalid phase") {
+        "wrong_phase"
+    } else if err.contains("is not the first player") {
+        "not_first_player"
+    } else if err == "Invalid card index" {
+        "invalid_card"
+    } else if err == "Invalid player" {
+        "invalid_player"
+    } else if err == "Not your seat" {
+        "not_your_seat"
+    } else if err == "Game is paused" {
+        "paused"
+    } else {
+        "other"
+    }
+}
@@ -0,0 +1,82 @@
+use crate::transport::LineWriter;
+use game_core::*;
+use std::sync::Arc;
+use tokio::sync::{Notify, mpsc};
+use tracing::{Instrument, warn};
+
+pub const OUTBOX_CAPACITY: usize = 256; // 每条连接最多积压的消息数
+
+// 连接的发送队列：所有消息按入队顺序由独立的写任务写出
+// 队列满说明客户端跟不上，直接断开它（重连后会拿到状态快照），而不是丢消息
+#[derive(Clone)]
+pub struct Outbox {
+    tx: mpsc::Sender<Option<String>>, // None 表示写完之前的消息后关闭
+    close: Arc<Notify>,
+}
+
+impl Outbox {
+    pub fn spawn(writer: LineWriter) -> Self {
+        let (tx, rx) = mpsc::channel(OUTBOX_CAPACITY);
+        let close = Arc::new(Notify::new());
+        tokio::spawn(write_loop(writer, rx, close.clone()).in_current_span());
+
+        Outbox { tx, close }
+    }
+
+    // 入队，不等待写出；失败时已断开该连接
+    pub fn send(&self, msg: &NetMessage) -> bool {
+        let text = serde_json::to_string(msg).unwrap();
+
+        match self.tx.try_send(Some(text)) {
+            Ok(()) => true,
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                warn!("outbox full, closing connection");
+                self.close();
+                false
+            }
+            // 写任务已退出，连接正在断开
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+        }
+    }
+
+    // 停止写任务并关闭写方向，客户端随即读到 EOF
+    pub fn close(&self) {
+        self.close.notify_one();
+    }
+
+    // 先写完已入队的消息（如踢出原因）再关闭
+    pub fn finish(&self) {
+        if self.tx.try_send(None).is_err() {
+            self.close();
+        }
+    }
+}
+
+async fn write_loop(
+    mut writer: LineWriter,
+    mut rx: mpsc::Receiver<Option<String>>,
+    close: Arc<Notify>,
+) {
+    loop {
+        let text = tokio::select! {
+            text = rx.recv() => match text {
+                Some(Some(text)) => text,
+                _ => break,
+            },
+            _ = close.notified() => break,
+        };
+
+        // 对端不读时 write_all 会一直挂起，同样要能被断开
+        tokio::select! {
+            result = writer.write_line(&text) => {
+                if let Err(err) = result {
+                    warn!(error = %err, "write failed");
+                    break;
+                }
+            }
+            _ = close.notified() => break,
+        }
+    }
+
+    writer.shutdown().await;
+}
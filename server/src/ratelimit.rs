@@ -0,0 +1,229 @@
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// 防滥用：单连接的消息频率和行长度、单个地址的连接数，多次违规的地址临时封禁
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_line_bytes: usize,       // 单条消息的最大字节数
+    pub messages_burst: u32,         // 所有消息合计，连发上限
+    pub messages_per_sec: f64,       // 之后每秒补充的条数
+    pub chat_burst: u32,             // 聊天和表情合计，连发上限
+    pub chat_per_sec: f64,           // 之后每秒补充的条数
+    pub max_connections_per_ip: u32, // 0 表示不限
+    pub ban_after_violations: u32,   // 窗口内违规达到此数即封禁，0 表示不封禁
+    pub violation_window_secs: u64,
+    pub ban_secs: u64,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            max_line_bytes: 8192,
+            messages_burst: 20,
+            messages_per_sec: 10.0,
+            chat_burst: 5,
+            chat_per_sec: 0.5,
+            max_connections_per_ip: 16,
+            ban_after_violations: 10,
+            violation_window_secs: 60,
+            ban_secs: 300,
+        }
+    }
+}
+
+impl LimitsConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_line_bytes < 256 {
+            return Err("Invalid limits max_line_bytes: must be at least 256".to_string());
+        }
+        if self.messages_burst == 0 || self.chat_burst == 0 {
+            return Err("Invalid limits burst: must be at least 1".to_string());
+        }
+        if !(self.messages_per_sec > 0.0 && self.chat_per_sec > 0.0) {
+            return Err("Invalid limits rate: must be greater than 0".to_string());
+        }
+        if self.ban_after_violations > 0 && self.violation_window_secs == 0 {
+            return Err("Invalid limits violation_window_secs: must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn message_bucket(&self) -> TokenBucket {
+        TokenBucket::new(self.messages_burst, self.messages_per_sec)
+    }
+
+    pub fn chat_bucket(&self) -> TokenBucket {
+        TokenBucket::new(self.chat_burst, self.chat_per_sec)
+    }
+}
+
+// 令牌桶：容量即允许的突发条数，之后按固定速率补充
+pub struct TokenBucket {
+    capacity: f64,
+    per_sec: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: u32, per_sec: f64) -> Self {
+        TokenBucket {
+            capacity: capacity as f64,
+            per_sec,
+            tokens: capacity as f64,
+            last: Instant::now(),
+        }
+    }
+
+    // 取一个令牌，桶空时返回 false
+    pub fn try_take(&mut self) -> bool {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last).as_secs_f64() * self.per_sec;
+        self.tokens = (self.tokens + refill).min(self.capacity);
+        self.last = now;
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+/* ================= 按来源地址 ================= */
+
+#[derive(Default)]
+struct Peer {
+    connections: u32,
+    violations: VecDeque<Instant>, // 窗口内的违规时间
+    banned_until: Option<Instant>,
+}
+
+impl Peer {
+    fn banned(&self, now: Instant) -> Option<Duration> {
+        self.banned_until
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+
+    // 没有连接、没有封禁、也没有近期违规的记录可以丢掉
+    fn forgettable(&self, now: Instant) -> bool {
+        self.connections == 0 && self.banned(now).is_none() && self.violations.is_empty()
+    }
+}
+
+// 所有连接共用，记录每个地址的连接数、违规和封禁
+pub struct Gatekeeper {
+    config: LimitsConfig,
+    peers: Mutex<HashMap<IpAddr, Peer>>,
+}
+
+impl Gatekeeper {
+    pub fn new(config: LimitsConfig) -> Self {
+        Gatekeeper {
+            config,
+            peers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> &LimitsConfig {
+        &self.config
+    }
+
+    // 接受新连接；返回的占位在连接结束时释放
+    pub fn admit(self: &Arc<Self>, ip: IpAddr) -> Result<IpSlot, String> {
+        let now = Instant::now();
+        let mut peers = self.peers.lock().unwrap();
+        let peer = peers.entry(ip).or_default();
+
+        if let Some(left) = peer.banned(now) {
+            return Err(format!(
+                "Temporarily banned, try again in {}s",
+                left.as_secs() + 1
+            ));
+        }
+        let max = self.config.max_connections_per_ip;
+        if max > 0 && peer.connections >= max {
+            return Err("Too many connections from your address".to_string());
+        }
+        peer.connections += 1;
+
+        Ok(IpSlot {
+            gatekeeper: self.clone(),
+            ip,
+        })
+    }
+
+    // 记一次违规；达到上限时封禁该地址并返回 true
+    fn violation(&self, ip: IpAddr) -> bool {
+        let threshold = self.config.ban_after_violations as usize;
+        if threshold == 0 {
+            return false;
+        }
+        let now = Instant::now();
+        let window = Duration::from_secs(self.config.violation_window_secs);
+        let mut peers = self.peers.lock().unwrap();
+        let peer = peers.entry(ip).or_default();
+
+        while peer
+            .violations
+            .front()
+            .is_some_and(|at| now.duration_since(*at) > window)
+        {
+            peer.violations.pop_front();
+        }
+        peer.violations.push_back(now);
+        if peer.violations.len() < threshold {
+            return false;
+        }
+
+        peer.violations.clear();
+        peer.banned_until = Some(now + Duration::from_secs(self.config.ban_secs));
+        true
+    }
+
+    fn release(&self, ip: IpAddr) {
+        let now = Instant::now();
+        let window = Duration::from_secs(self.config.violation_window_secs);
+        let mut peers = self.peers.lock().unwrap();
+        if let Some(peer) = peers.get_mut(&ip) {
+            peer.connections = peer.connections.saturating_sub(1);
+        }
+        // 顺便清掉过期的记录，免得表一直增长
+        peers.retain(|_, peer| {
+            if peer.banned(now).is_none() {
+                peer.banned_until = None;
+            }
+            peer.violations
+                .retain(|at| now.duration_since(*at) <= window);
+            !peer.forgettable(now)
+        });
+    }
+}
+
+// 一条连接占用的名额
+pub struct IpSlot {
+    gatekeeper: Arc<Gatekeeper>,
+    ip: IpAddr,
+}
+
+impl IpSlot {
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+
+    // 记一次违规；该地址因此被封禁时返回 true
+    pub fn violation(&self) -> bool {
+        self.gatekeeper.violation(self.ip)
+    }
+}
+
+impl Drop for IpSlot {
+    fn drop(&mut self) {
+        self.gatekeeper.release(self.ip);
+    }
+}
@@ -0,0 +1,94 @@
+use serde::Deserialize;
+
+// 多人 Elo：一局按最终名次拆成两两对局，每对按 Elo 期望结算，再按对手数平均
+// 游客也算对手，按初始分计，自己的分不变；机器人不参与，至少两个账号才计分
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RatingConfig {
+    pub initial: f64,      // 新账号的分数，也是游客的分数
+    pub k_factor: f64,     // 一局最多变化的分数
+    pub matchmaking: bool, // 快速加入时优先选平均分相近的房间
+    pub match_range: f64,  // 平均分差距超过此值的房间不选，除非没有别的
+}
+
+impl Default for RatingConfig {
+    fn default() -> Self {
+        RatingConfig {
+            initial: 1500.0,
+            k_factor: 32.0,
+            matchmaking: false,
+            match_range: 200.0,
+        }
+    }
+}
+
+impl RatingConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.initial.is_finite() && self.initial > 0.0) {
+            return Err("Invalid rating initial: must be greater than 0".to_string());
+        }
+        if !(self.k_factor.is_finite() && self.k_factor > 0.0) {
+            return Err("Invalid rating k_factor: must be greater than 0".to_string());
+        }
+        if !self.match_range.is_finite() || self.match_range < 0.0 {
+            return Err("Invalid rating match_range: must not be negative".to_string());
+        }
+        Ok(())
+    }
+
+    // 每个座位的分数变化；placements 为 1 起的名次，同名次算平局
+    pub fn changes(&self, ratings: &[f64], placements: &[usize]) -> Vec<f64> {
+        let n = ratings.len();
+        if n < 2 {
+            return vec![0.0; n];
+        }
+        (0..n)
+            .map(|i| {
+                let total: f64 = (0..n)
+                    .filter(|&j| j != i)
+                    .map(|j| {
+                        let actual = match placements[i].cmp(&placements[j]) {
+                            std::cmp::Ordering::Less => 1.0,
+                            std::cmp::Ordering::Equal => 0.5,
+                            std::cmp::Ordering::Greater => 0.0,
+                        };
+                        actual - expected(ratings[i], ratings[j])
+                    })
+                    .sum();
+                self.k_factor * total / (n - 1) as f64
+            })
+            .collect()
+    }
+}
+
+// 分数为 a 的一方对 b 的期望得分
+fn expected(a: f64, b: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((b - a) / 400.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expected_scores_are_symmetric() {
+        for (a, b) in [(1500.0, 1500.0), (1600.0, 1400.0), (1200.0, 2000.0)] {
+            let total = expected(a, b) + expected(b, a);
+            assert!((total - 1.0).abs() < 1e-9);
+        }
+        assert_eq!(expected(1500.0, 1500.0), 0.5);
+    }
+
+    #[test]
+    fn changes_sum_to_zero() {
+        let config = RatingConfig::default();
+        let changes = config.changes(&[1500.0, 1620.0, 1410.0], &[2, 1, 2]);
+        assert!(changes.iter().sum::<f64>().abs() < 1e-9);
+        // 名次相同的两人，分数低的一方涨得更多
+        assert!(changes[2] > changes[0]);
+
+        // 两人同分，赢家拿一半 K
+        let changes = config.changes(&[1500.0, 1500.0], &[1, 2]);
+        assert_eq!(changes, vec![16.0, -16.0]);
+    }
+}
@@ -0,0 +1,1699 @@
+use crate::accounts::Account;
+use crate::cast::CastLog;
+use crate::journal::{Journal, JournalEntry};
+use crate::metrics::METRICS;
+use crate::outbox::Outbox;
+use crate::snapshot::RoomSnapshot;
+use crate::storage::{NewGame, SavedGame, SeatRecord, Storage};
+use game_core::*;
+use rand::Rng;
+use rand::distr::Alphanumeric;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{Instrument, error, info, info_span, warn};
+
+pub const MAX_SEATS: usize = 5; // 牌堆最多支持 5 人
+pub const MAX_SPECTATORS: usize = 20; // 每个房间的观众上限
+pub const MAX_CHAT_LEN: usize = 200; // 聊天消息的最大字符数
+pub const VOTE_SECS: u64 = 30; // 投票的表态时限，过时作废
+pub const RECONNECT_GRACE_SECS: u64 = 30; // 游戏中掉线后等待重连的时间，过后由机器人代打
+
+static NEXT_CONN_ID: AtomicU64 = AtomicU64::new(1);
+
+// 为每条连接分配唯一编号，用于判断座位是否已被重连顶替
+pub fn next_conn_id() -> u64 {
+    NEXT_CONN_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerPhase {
+    Waiting,  // 等人
+    Playing,  // 游戏中
+    Finished, // 已结束，等待是否重开
+}
+
+// 入座情况（等人阶段的准备状态、房主也在这里）
+#[derive(Default)]
+pub struct Seating {
+    pub sessions: HashMap<String, usize>,   // 会话令牌 -> player_id
+    pub accounts: HashMap<String, Account>, // 会话令牌 -> 登录的账号，游客没有
+    pub conns: HashMap<usize, u64>,         // 座位 -> 当前在线连接编号
+    pub bots: HashSet<usize>,               // 机器人座位
+    pub ready: HashSet<usize>,              // 已准备的座位
+    pub host: Option<usize>,                // 房主座位
+    pub muted: HashSet<usize>,              // 被禁言的座位
+    pub dropped: HashMap<usize, Instant>,   // 游戏中掉线的座位 -> 掉线时间
+}
+
+impl Seating {
+    fn is_taken(&self, seat: usize) -> bool {
+        self.bots.contains(&seat) || self.sessions.values().any(|p| *p == seat)
+    }
+
+    // 已入座人数（含机器人）
+    fn occupied(&self) -> usize {
+        self.sessions.len() + self.bots.len()
+    }
+
+    // 由机器人代为行动的座位：机器人座位，以及掉线超过宽限期的玩家
+    fn taken_over(&self) -> HashSet<usize> {
+        let grace = Duration::from_secs(RECONNECT_GRACE_SECS);
+        let mut seats = self.bots.clone();
+        seats.extend(self.sessions.values().filter(|seat| {
+            !self.conns.contains_key(seat)
+                && self
+                    .dropped
+                    .get(seat)
+                    .is_none_or(|since| since.elapsed() >= grace)
+        }));
+        seats
+    }
+
+    pub fn token_of(&self, seat: usize) -> Option<&String> {
+        self.sessions
+            .iter()
+            .find(|(_, p)| **p == seat)
+            .map(|(token, _)| token)
+    }
+
+    pub fn account_of(&self, seat: usize) -> Option<&Account> {
+        self.token_of(seat)
+            .and_then(|token| self.accounts.get(token))
+    }
+
+    // 释放座位，房主离开时由座位号最小的玩家接任
+    fn remove(&mut self, seat: usize) {
+        self.sessions.retain(|_, p| *p != seat);
+        let sessions = &self.sessions;
+        self.accounts
+            .retain(|token, _| sessions.contains_key(token));
+        self.conns.remove(&seat);
+        self.bots.remove(&seat);
+        self.ready.remove(&seat);
+        self.muted.remove(&seat);
+        self.dropped.remove(&seat);
+        self.hand_over_host(seat);
+    }
+
+    // seat 不再有真人时，房主交给座位号最小的玩家，没有玩家则空缺
+    fn hand_over_host(&mut self, seat: usize) {
+        if self.host == Some(seat) {
+            self.host = self.sessions.values().copied().min();
+        }
+    }
+
+    // 交换两个座位上的玩家（空座位也可以），双方都需要重新准备
+    fn swap(&mut self, a: usize, b: usize) {
+        for seat in self.sessions.values_mut() {
+            if *seat == a {
+                *seat = b;
+            } else if *seat == b {
+                *seat = a;
+            }
+        }
+        let (conn_a, conn_b) = (self.conns.remove(&a), self.conns.remove(&b));
+        if let Some(conn) = conn_a {
+            self.conns.insert(b, conn);
+        }
+        if let Some(conn) = conn_b {
+            self.conns.insert(a, conn);
+        }
+
+        let (bot_a, bot_b) = (self.bots.remove(&a), self.bots.remove(&b));
+        if bot_a {
+            self.bots.insert(b);
+            self.ready.insert(b);
+        }
+        if bot_b {
+            self.bots.insert(a);
+            self.ready.insert(a);
+        }
+        if !bot_a {
+            self.ready.remove(&b);
+        }
+        if !bot_b {
+            self.ready.remove(&a);
+        }
+        let (muted_a, muted_b) = (self.muted.remove(&a), self.muted.remove(&b));
+        if muted_a {
+            self.muted.insert(b);
+        }
+        if muted_b {
+            self.muted.insert(a);
+        }
+        if self.host == Some(a) {
+            self.host = Some(b);
+        } else if self.host == Some(b) {
+            self.host = Some(a);
+        }
+    }
+}
+
+// 一个房间：独立的一局游戏及其连接
+pub struct Room {
+    pub id: u32,
+    pub name: String,
+    pub seats: usize,
+    pub rules: GameRules,
+    pub invite_code: Option<String>, // 私密房间的邀请码，公开房间为 None
+    password: Option<String>,        // 入座密码
+    pub game: Mutex<GameState>,
+    pub clients: Mutex<HashMap<usize, Outbox>>, // player_id -> 连接的发送队列
+    pub spectators: Mutex<HashMap<u64, Outbox>>, // 连接编号 -> 观众的发送队列，只收公开消息
+    pub cast: Mutex<CastLog>,                   // 解说视角的延迟事件流
+    pub seating: Mutex<Seating>,
+    pub phase: Mutex<ServerPhase>,
+    storage: Arc<Storage>,
+    game_id: std::sync::Mutex<Option<i64>>, // 开局后在数据库中的编号，重开时清空
+    journal: std::sync::Mutex<Option<Journal>>, // 开局后的预写日志，对局结束时取走
+    paused: AtomicBool,                     // 暂停时不接受指令，计时和机器人也停下
+    pub score_history: Mutex<Vec<Vec<i32>>>, // 每轮的分数变化
+    round_started: Mutex<Instant>,          // 本轮开始的时间，用于统计每轮用时
+}
+
+impl Room {
+    pub fn new(
+        id: u32,
+        name: String,
+        seats: usize,
+        rules: GameRules,
+        invite_code: Option<String>,
+        password: Option<String>,
+        storage: Arc<Storage>,
+    ) -> Self {
+        Room {
+            id,
+            name,
+            seats,
+            rules,
+            invite_code,
+            password,
+            game: Mutex::new(init_game(seats, rules)),
+            clients: Mutex::new(HashMap::new()),
+            spectators: Mutex::new(HashMap::new()),
+            cast: Mutex::new(CastLog::default()),
+            seating: Mutex::new(Seating::default()),
+            phase: Mutex::new(ServerPhase::Waiting),
+            storage,
+            game_id: std::sync::Mutex::new(None),
+            journal: std::sync::Mutex::new(None),
+            paused: AtomicBool::new(false),
+            score_history: Mutex::new(vec![]),
+            round_started: Mutex::new(Instant::now()),
+        }
+    }
+
+    // 从日志或数据库恢复进行中的对局：按记下的发牌事件还原手牌，再依次重放指令
+    // 玩家凭原会话令牌重连；恢复后同样给他们一段重连的宽限期
+    pub fn resume(
+        saved: SavedGame,
+        storage: Arc<Storage>,
+        journal: Option<Journal>,
+    ) -> Result<Self, String> {
+        let mut game = init_game(saved.seats, saved.rules);
+        let mut cast = CastLog::default();
+        cast.push(game.round, None, game.deal_recorded(&saved.dealt)?);
+        game.is_card = true;
+        let mut score_history = vec![];
+        for cmd in saved.commands {
+            let round = game.round;
+            let events = game.apply(cmd.clone())?;
+            for event in &events {
+                if let Event::RoundResult { score_delta, .. } = event {
+                    score_history.push(score_delta.clone());
+                }
+            }
+            cast.push(round, Some(cmd), events);
+        }
+        if game.phase == Phase::End {
+            return Err("Game already ended".to_string());
+        }
+
+        let mut seating = Seating::default();
+        let now = Instant::now();
+        for player in saved.players {
+            match player.token {
+                Some(token) => {
+                    if let Some(account) = player.account {
+                        seating.accounts.insert(token.clone(), account);
+                    }
+                    seating.sessions.insert(token, player.seat);
+                    seating.dropped.insert(player.seat, now);
+                    seating.host.get_or_insert(player.seat);
+                }
+                None => {
+                    seating.bots.insert(player.seat);
+                }
+            }
+            seating.ready.insert(player.seat);
+        }
+
+        Ok(Room {
+            id: saved.room_id,
+            name: saved.room_name,
+            seats: saved.seats,
+            rules: saved.rules,
+            invite_code: saved.invite_code,
+            password: saved.password,
+            game: Mutex::new(game),
+            clients: Mutex::new(HashMap::new()),
+            spectators: Mutex::new(HashMap::new()),
+            cast: Mutex::new(cast),
+            seating: Mutex::new(seating),
+            phase: Mutex::new(ServerPhase::Playing),
+            storage,
+            game_id: std::sync::Mutex::new(saved.game_id),
+            journal: std::sync::Mutex::new(journal),
+            paused: AtomicBool::new(false),
+            score_history: Mutex::new(score_history),
+            round_started: Mutex::new(Instant::now()),
+        })
+    }
+
+    // 从停机快照还原进行中的对局
+    pub fn from_snapshot(
+        mut snapshot: RoomSnapshot,
+        storage: Arc<Storage>,
+        journal: Option<Journal>,
+    ) -> Self {
+        // 投票的时限不跨重启，未决的投票作废
+        snapshot.state.vote = None;
+        let now = Instant::now();
+        let dropped = snapshot
+            .sessions
+            .values()
+            .map(|seat| (*seat, now))
+            .collect();
+        let seating = Seating {
+            sessions: snapshot.sessions,
+            accounts: snapshot.accounts,
+            conns: HashMap::new(),
+            bots: snapshot.bots.into_iter().collect(),
+            ready: (0..snapshot.seats).collect(),
+            host: snapshot.host,
+            muted: HashSet::new(),
+            dropped,
+        };
+
+        Room {
+            id: snapshot.room_id,
+            name: snapshot.room_name,
+            seats: snapshot.seats,
+            rules: snapshot.rules,
+            invite_code: snapshot.invite_code,
+            password: snapshot.password,
+            game: Mutex::new(snapshot.state),
+            clients: Mutex::new(HashMap::new()),
+            spectators: Mutex::new(HashMap::new()),
+            cast: Mutex::new(CastLog::from_steps(snapshot.cast)),
+            seating: Mutex::new(seating),
+            phase: Mutex::new(ServerPhase::Playing),
+            storage,
+            game_id: std::sync::Mutex::new(snapshot.game_id),
+            journal: std::sync::Mutex::new(journal),
+            paused: AtomicBool::new(snapshot.paused),
+            score_history: Mutex::new(snapshot.score_history),
+            round_started: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub async fn info(&self) -> RoomInfo {
+        let started = !matches!(*self.phase.lock().await, ServerPhase::Waiting);
+        let (players, bots, ratings) = {
+            let seating = self.seating.lock().await;
+            (
+                seating.sessions.len(),
+                seating.bots.len(),
+                self.ratings(&seating),
+            )
+        };
+        let spectators = self.spectators.lock().await.len();
+        let rating = (!ratings.is_empty())
+            .then(|| (ratings.values().sum::<f64>() / ratings.len() as f64).round() as i32);
+
+        RoomInfo {
+            room_id: self.id,
+            name: self.name.clone(),
+            seats: self.seats,
+            players,
+            bots,
+            rules: self.rules,
+            started,
+            locked: self.password.is_some(),
+            spectators,
+            rating,
+        }
+    }
+
+    // 登录玩家所在座位 -> 当前分数
+    fn ratings(&self, seating: &Seating) -> BTreeMap<usize, f64> {
+        seating
+            .sessions
+            .values()
+            .filter_map(|seat| {
+                let account = seating.account_of(*seat)?;
+                Some((*seat, account.rating.unwrap_or(self.storage.rating.initial)))
+            })
+            .collect()
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.invite_code.is_some()
+    }
+
+    // 密码校验
+    pub fn check_password(&self, password: Option<&str>) -> Result<(), String> {
+        match &self.password {
+            Some(expected) if password != Some(expected.as_str()) => {
+                Err("Wrong password".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // 会话令牌当前对应的座位
+    pub async fn player_of(&self, token: &str) -> Option<usize> {
+        self.seating.lock().await.sessions.get(token).copied()
+    }
+
+    // 是否已无人入座
+    pub async fn is_empty(&self) -> bool {
+        self.seating.lock().await.sessions.is_empty()
+    }
+
+    // 入座：分配最小的空座位并发放会话令牌，第一个入座的人成为房主
+    // 同一账号在一个房间里只能占一个座位
+    pub async fn join(
+        &self,
+        outbox: Outbox,
+        conn_id: u64,
+        account: Option<Account>,
+    ) -> Result<(usize, String), String> {
+        let phase_guard = self.phase.lock().await;
+        if !matches!(*phase_guard, ServerPhase::Waiting) {
+            // 游戏中 / 已结束，不接新玩家
+            return Err("Game already started".to_string());
+        }
+
+        let mut seating = self.seating.lock().await;
+        let Some(player_id) = (0..self.seats).find(|id| !seating.is_taken(*id)) else {
+            return Err("Player limit reached".to_string());
+        };
+        if let Some(account) = &account
+            && seating
+                .accounts
+                .values()
+                .any(|seated| seated.id == account.id)
+        {
+            return Err("Account already seated in this room".to_string());
+        }
+
+        let token = new_session_token();
+        seating.sessions.insert(token.clone(), player_id);
+        if let Some(account) = account {
+            seating.accounts.insert(token.clone(), account);
+        }
+        seating.conns.insert(player_id, conn_id);
+        seating.host.get_or_insert(player_id);
+        drop(seating);
+
+        self.clients.lock().await.insert(player_id, outbox);
+        self.send_message(player_id, &self.joined_message()).await;
+        self.send_to_player(
+            player_id,
+            &Event::PlayerAssigned {
+                player_id,
+                token: token.clone(),
+            },
+        )
+        .await;
+        self.broadcast_room_state().await;
+
+        Ok((player_id, token))
+    }
+
+    // 凭会话令牌取回座位
+    pub async fn reconnect(&self, token: &str, outbox: Outbox, conn_id: u64) -> Option<usize> {
+        let player_id = {
+            let mut seating = self.seating.lock().await;
+            let player_id = seating.sessions.get(token).copied()?;
+
+            // 旧连接可能尚未被发现断开，直接用新连接顶替
+            seating.conns.insert(player_id, conn_id);
+            seating.dropped.remove(&player_id);
+            player_id
+        };
+        // 旧连接的发送队列随之被替换
+        if let Some(old) = self.clients.lock().await.insert(player_id, outbox) {
+            old.close();
+        }
+        self.send_message(player_id, &self.joined_message()).await;
+
+        if matches!(*self.phase.lock().await, ServerPhase::Waiting) {
+            let state = self.room_state().await;
+            self.send_message(player_id, &state).await;
+        } else {
+            let snapshot = self.game.lock().await.snapshot(player_id);
+            if let Some(snapshot) = snapshot {
+                self.send_to_player(player_id, &snapshot).await;
+            }
+        }
+
+        Some(player_id)
+    }
+
+    fn joined_message(&self) -> NetMessage {
+        NetMessage::RoomJoined {
+            room_id: self.id,
+            invite_code: self.invite_code.clone(),
+        }
+    }
+
+    /* ================= 聊天 / 表情 ================= */
+
+    // 去掉首尾空白后转发给房间内所有人（含观众）
+    pub async fn chat(&self, player_id: usize, text: &str) -> Result<(), String> {
+        self.check_muted(player_id).await?;
+        let text = text.trim();
+        if text.is_empty() {
+            return Err("Empty message".to_string());
+        }
+        if text.chars().count() > MAX_CHAT_LEN {
+            return Err(format!(
+                "Message too long, at most {} characters",
+                MAX_CHAT_LEN
+            ));
+        }
+        if text.chars().any(char::is_control) {
+            return Err("Message contains control characters".to_string());
+        }
+
+        self.broadcast_message(&NetMessage::ChatMessage {
+            player_id,
+            text: text.to_string(),
+        })
+        .await;
+        Ok(())
+    }
+
+    pub async fn emote(&self, player_id: usize, emote: Emote) -> Result<(), String> {
+        self.check_muted(player_id).await?;
+        self.broadcast_message(&NetMessage::EmoteMessage { player_id, emote })
+            .await;
+        Ok(())
+    }
+
+    async fn check_muted(&self, player_id: usize) -> Result<(), String> {
+        if self.seating.lock().await.muted.contains(&player_id) {
+            return Err("You are muted".to_string());
+        }
+        Ok(())
+    }
+
+    // 禁言或解除禁言，并告知本人
+    pub async fn set_muted(&self, seat: usize, muted: bool) -> Result<(), String> {
+        {
+            let mut seating = self.seating.lock().await;
+            if seating.token_of(seat).is_none() {
+                return Err("No player in that seat".to_string());
+            }
+            let changed = if muted {
+                seating.muted.insert(seat)
+            } else {
+                seating.muted.remove(&seat)
+            };
+            if !changed {
+                return Err(if muted {
+                    "Player already muted".to_string()
+                } else {
+                    "Player not muted".to_string()
+                });
+            }
+        }
+
+        let text = if muted {
+            "You have been muted by an administrator"
+        } else {
+            "You are no longer muted"
+        };
+        self.send_message(
+            seat,
+            &NetMessage::Notice {
+                text: text.to_string(),
+            },
+        )
+        .await;
+        Ok(())
+    }
+
+    /* ================= 观众 ================= */
+
+    // 观众进入：先下发当前的公开状态，之后随广播收到公开事件
+    pub async fn add_spectator(&self, outbox: Outbox, conn_id: u64) -> Result<(), String> {
+        let phase = *self.phase.lock().await;
+        if phase == ServerPhase::Waiting {
+            let state = self.room_state().await;
+            self.register_spectator(outbox, conn_id, &state).await
+        } else {
+            // 事件在持有游戏锁时分发，持锁登记不会漏掉或重复
+            let game = self.game.lock().await;
+            let snapshot = NetMessage::Event(game.public_snapshot());
+            self.register_spectator(outbox, conn_id, &snapshot).await
+        }
+    }
+
+    async fn register_spectator(
+        &self,
+        outbox: Outbox,
+        conn_id: u64,
+        state: &NetMessage,
+    ) -> Result<(), String> {
+        let mut spectators = self.spectators.lock().await;
+        if spectators.len() >= MAX_SPECTATORS {
+            return Err("Spectator limit reached".to_string());
+        }
+        outbox.send(&NetMessage::Spectating { room_id: self.id });
+        outbox.send(state);
+        spectators.insert(conn_id, outbox);
+        Ok(())
+    }
+
+    // 观众或解说离开
+    pub async fn remove_spectator(&self, conn_id: u64) {
+        self.spectators.lock().await.remove(&conn_id);
+        self.cast.lock().await.remove_viewer(conn_id);
+    }
+
+    // 解说进入：补发本局已到时的部分
+    pub async fn add_caster(
+        &self,
+        outbox: Outbox,
+        conn_id: u64,
+        delay_rounds: u8,
+    ) -> Result<(), String> {
+        let game = self.game.lock().await;
+        let mut cast = self.cast.lock().await;
+        if cast.viewers() >= MAX_SPECTATORS {
+            return Err("Spectator limit reached".to_string());
+        }
+        outbox.send(&NetMessage::Casting {
+            room_id: self.id,
+            delay_rounds,
+        });
+        cast.add_viewer(conn_id, outbox, delay_rounds, &game);
+        Ok(())
+    }
+
+    // 房间关闭时请走所有观众和解说
+    pub async fn close_spectators(&self, reason: &str) {
+        let casters = self.cast.lock().await.drain_viewers();
+        let mut spectators = self.spectators.lock().await;
+        for outbox in spectators.drain().map(|(_, o)| o).chain(casters) {
+            outbox.send(&NetMessage::RoomClosed {
+                reason: reason.to_string(),
+            });
+            outbox.finish();
+        }
+    }
+
+    // 离座：仅等人阶段允许，释放座位
+    pub async fn leave(&self, player_id: usize) -> Result<(), String> {
+        let phase_guard = self.phase.lock().await;
+        if matches!(*phase_guard, ServerPhase::Playing) {
+            return Err("Game in progress".to_string());
+        }
+
+        self.seating.lock().await.remove(player_id);
+        self.clients.lock().await.remove(&player_id);
+        if matches!(*phase_guard, ServerPhase::Waiting) {
+            self.broadcast_room_state().await;
+        }
+
+        Ok(())
+    }
+
+    // 连接断开：座位仍属于本连接时才处理；游戏中保留座位等重连，其他阶段直接释放
+    // 游戏中掉线的玩家过了宽限期才由机器人代打，期间轮到他时仍按行动时限超时处理
+    pub async fn disconnect(&self, token: &str, conn_id: u64) -> Option<usize> {
+        let phase_guard = self.phase.lock().await;
+        let player_id = {
+            let mut seating = self.seating.lock().await;
+            let player_id = seating.sessions.get(token).copied()?;
+            if seating.conns.get(&player_id) != Some(&conn_id) {
+                // 座位已被重连顶替
+                return None;
+            }
+
+            if matches!(*phase_guard, ServerPhase::Playing) {
+                seating.conns.remove(&player_id);
+                seating.dropped.insert(player_id, Instant::now());
+            } else {
+                seating.remove(player_id);
+            }
+            player_id
+        };
+        self.clients.lock().await.remove(&player_id);
+
+        if matches!(*phase_guard, ServerPhase::Waiting) {
+            self.broadcast_room_state().await;
+        }
+
+        Some(player_id)
+    }
+
+    // 连接错过心跳或恢复时通知同桌
+    pub async fn set_idle(&self, player_id: usize, idle: bool) {
+        self.broadcast_message(&NetMessage::PlayerIdle { player_id, idle })
+            .await;
+    }
+
+    /* ================= 等人阶段：准备 / 换座 / 开局 ================= */
+
+    pub async fn set_ready(&self, player_id: usize, ready: bool) -> Result<(), String> {
+        let phase_guard = self.phase.lock().await;
+        if !matches!(*phase_guard, ServerPhase::Waiting) {
+            return Err("Game in progress".to_string());
+        }
+
+        {
+            let mut seating = self.seating.lock().await;
+            if ready {
+                seating.ready.insert(player_id);
+            } else {
+                seating.ready.remove(&player_id);
+            }
+        }
+        self.broadcast_room_state().await;
+
+        Ok(())
+    }
+
+    // 换到指定座位；座位上有人则与其互换
+    pub async fn swap_seat(&self, player_id: usize, seat: usize) -> Result<usize, String> {
+        let phase_guard = self.phase.lock().await;
+        if !matches!(*phase_guard, ServerPhase::Waiting) {
+            return Err("Game in progress".to_string());
+        }
+        if seat >= self.seats {
+            return Err(format!("Invalid seat: {}", seat));
+        }
+        if seat == player_id {
+            return Ok(seat);
+        }
+
+        let (moved, other) = {
+            let mut seating = self.seating.lock().await;
+            seating.swap(player_id, seat);
+            (
+                seating.token_of(seat).cloned(),
+                seating.token_of(player_id).cloned(),
+            )
+        };
+
+        {
+            let mut clients = self.clients.lock().await;
+            let a = clients.remove(&player_id);
+            let b = clients.remove(&seat);
+            if let Some(outbox) = a {
+                clients.insert(seat, outbox);
+            }
+            if let Some(outbox) = b {
+                clients.insert(player_id, outbox);
+            }
+        }
+
+        // 通知双方新的 player_id
+        if let Some(token) = moved {
+            self.send_to_player(
+                seat,
+                &Event::PlayerAssigned {
+                    player_id: seat,
+                    token,
+                },
+            )
+            .await;
+        }
+        if let Some(token) = other {
+            self.send_to_player(player_id, &Event::PlayerAssigned { player_id, token })
+                .await;
+        }
+        self.broadcast_room_state().await;
+
+        Ok(seat)
+    }
+
+    // 房主用机器人补满空座位
+    pub async fn add_bots(&self, player_id: usize) -> Result<(), String> {
+        let phase_guard = self.phase.lock().await;
+        if !matches!(*phase_guard, ServerPhase::Waiting) {
+            return Err("Game in progress".to_string());
+        }
+        if self.seating.lock().await.host != Some(player_id) {
+            return Err("Only the host can add bots".to_string());
+        }
+
+        self.fill_seats().await;
+        Ok(())
+    }
+
+    // 房主移除某个座位上的机器人
+    pub async fn remove_bot(&self, player_id: usize, seat: usize) -> Result<(), String> {
+        let phase_guard = self.phase.lock().await;
+        if !matches!(*phase_guard, ServerPhase::Waiting) {
+            return Err("Game in progress".to_string());
+        }
+
+        {
+            let mut seating = self.seating.lock().await;
+            if seating.host != Some(player_id) {
+                return Err("Only the host can remove bots".to_string());
+            }
+            if !seating.bots.contains(&seat) {
+                return Err(format!("Seat {} is not a bot", seat));
+            }
+            seating.remove(seat);
+        }
+        self.broadcast_room_state().await;
+
+        Ok(())
+    }
+
+    // 等人超时：还有真人在等就用机器人补满
+    pub async fn waiting_timeout(&self) {
+        let phase_guard = self.phase.lock().await;
+        if !matches!(*phase_guard, ServerPhase::Waiting) || self.is_empty().await {
+            return;
+        }
+
+        self.fill_seats().await;
+    }
+
+    // 调用方需持有 phase 锁且处于等人阶段
+    async fn fill_seats(&self) {
+        {
+            let mut seating = self.seating.lock().await;
+            for seat in 0..self.seats {
+                if !seating.is_taken(seat) {
+                    seating.bots.insert(seat);
+                    seating.ready.insert(seat);
+                }
+            }
+        }
+        self.broadcast_room_state().await;
+    }
+
+    // 房主开局：座位坐满且全部准备
+    pub async fn start(self: &Arc<Self>, player_id: usize) -> Result<(), String> {
+        let mut phase_guard = self.phase.lock().await;
+        if !matches!(*phase_guard, ServerPhase::Waiting) {
+            return Err("Game in progress".to_string());
+        }
+
+        {
+            let seating = self.seating.lock().await;
+            if seating.host != Some(player_id) {
+                return Err("Only the host can start the game".to_string());
+            }
+            if seating.occupied() < self.seats {
+                return Err("Not all seats are taken".to_string());
+            }
+            if seating.ready.len() < self.seats {
+                return Err("Not everyone is ready".to_string());
+            }
+        }
+
+        if !self.game.lock().await.is_card {
+            *phase_guard = ServerPhase::Playing;
+            self.start_game().await;
+            self.game.lock().await.is_card = true;
+        }
+        drop(phase_guard);
+
+        self.start_timer();
+
+        // 首位行动的可能是机器人
+        self.run_bots().await;
+        Ok(())
+    }
+
+    /* ================= 游戏中：指令 / 事件分发 / 机器人 ================= */
+
+    // 执行玩家指令并分发事件，随后轮到的机器人接着行动
+    // 行动计时，随房间释放而结束
+    pub fn start_timer(self: &Arc<Self>) {
+        tokio::spawn(
+            turn_timer(Arc::downgrade(self)).instrument(info_span!("room", room_id = self.id)),
+        );
+    }
+
+    pub async fn apply(self: &Arc<Self>, cmd: Command) -> Result<(), String> {
+        // 投票不是行棋：暂停中也可以投，不写日志
+        if let Command::Vote {
+            player_id,
+            motion,
+            yes,
+        } = cmd
+        {
+            return self.vote(player_id, motion, yes).await;
+        }
+
+        {
+            // 持锁后再检查，停机时冻结房间与指令不会交错
+            let mut game = self.game.lock().await;
+            if self.is_paused() {
+                return Err("Game is paused".to_string());
+            }
+            self.play(&mut game, cmd).await?;
+        }
+
+        self.run_bots().await;
+        self.check_finished().await;
+        Ok(())
+    }
+
+    // 执行一条指令：先写日志和数据库，再分发产生的事件
+    async fn play(&self, game: &mut GameState, cmd: Command) -> Result<(), String> {
+        let round = game.round;
+        let events = game.apply(cmd.clone())?;
+        {
+            let mut cast = self.cast.lock().await;
+            cast.push(round, Some(cmd.clone()), events.clone());
+            cast.release(game);
+        }
+        if let Some(journal) = self.journal.lock().unwrap().as_ref()
+            && let Err(err) = journal.append(&JournalEntry::Step {
+                command: cmd.clone(),
+                events: events.clone(),
+            })
+        {
+            error!(room_id = self.id, error = %err, "journal write failed");
+        }
+        self.record(Some(&cmd), &events);
+        self.dispatch(&events).await;
+        Ok(())
+    }
+
+    fn game_id(&self) -> Option<i64> {
+        *self.game_id.lock().unwrap()
+    }
+
+    // 交给写线程，不等写完；记录失败只打印，不影响对局
+    fn record(&self, cmd: Option<&Command>, events: &[Event]) {
+        if let Some(game_id) = self.game_id() {
+            self.storage.record(game_id, cmd, events);
+        }
+    }
+
+    async fn dispatch(&self, events: &[Event]) {
+        for event in events {
+            match event {
+                //
+                Event::PredictionAccepted { .. }
+                | Event::CardPlayed { .. }
+                | Event::PosteriorPredictionAccepted { .. }
+                | Event::RoundResult { .. }
+                | Event::PhaseChanged
+                | Event::GameEnded
+                | Event::VoteStarted { .. }
+                | Event::VoteCast { .. }
+                | Event::VoteEnded { .. } => {
+                    self.broadcast(event).await;
+                }
+                Event::CardsDealt { player_id, .. } => {
+                    self.send_to_player(*player_id, event).await;
+                }
+                _ => {}
+            }
+            if let Event::RoundResult { score_delta, .. } = event {
+                self.score_history.lock().await.push(score_delta.clone());
+
+                let mut round_started = self.round_started.lock().await;
+                METRICS.round_finished(round_started.elapsed());
+                *round_started = Instant::now();
+            }
+        }
+    }
+
+    // 由机器人代为行动的座位，暂停时没有
+    async fn bot_controlled(&self) -> HashSet<usize> {
+        if !matches!(*self.phase.lock().await, ServerPhase::Playing) || self.is_paused() {
+            return HashSet::new();
+        }
+
+        self.seating.lock().await.taken_over()
+    }
+
+    // 机器人座位和掉线玩家的座位
+    async fn unattended(&self) -> HashSet<usize> {
+        let seating = self.seating.lock().await;
+        let mut seats = seating.bots.clone();
+        seats.extend(
+            seating
+                .sessions
+                .values()
+                .filter(|seat| !seating.conns.contains_key(seat)),
+        );
+        seats
+    }
+
+    // 轮到机器人时通过 GameState::apply 代为行动，直到轮到在线玩家
+    pub async fn run_bots(&self) {
+        loop {
+            let controlled = self.bot_controlled().await;
+            let mut game = self.game.lock().await;
+
+            let Some(cmd) = game
+                .actor()
+                .filter(|seat| controlled.contains(seat))
+                .and_then(|seat| bot_command(&game, seat))
+            else {
+                break;
+            };
+
+            if let Err(err) = self.play(&mut game, cmd).await {
+                warn!(room_id = self.id, error = %err, "bot move rejected");
+                break;
+            }
+        }
+
+        self.check_finished().await;
+    }
+
+    // 行动超时：若仍是同一轮同一阶段的同一玩家，按机器人策略代为行动
+    async fn time_out(&self, turn: (u8, Phase, usize)) {
+        {
+            let mut game = self.game.lock().await;
+            let (round, phase, seat) = turn;
+            if self.is_paused() {
+                return;
+            }
+            if game.round != round || game.phase != phase || game.actor() != Some(seat) {
+                // 恰好已经行动
+                return;
+            }
+            if let Err(err) = self.act_for(&mut game, seat).await {
+                warn!(room_id = self.id, seat, error = %err, "timeout move rejected");
+            }
+        }
+
+        self.run_bots().await;
+    }
+
+    // 管理员强制推进：当前行动的玩家按超时处理
+    pub async fn force_advance(&self) -> Result<usize, String> {
+        if !matches!(*self.phase.lock().await, ServerPhase::Playing) {
+            return Err("Game not in progress".to_string());
+        }
+        let seat = {
+            let mut game = self.game.lock().await;
+            if self.is_paused() {
+                return Err("Game is paused".to_string());
+            }
+            let seat = game.actor().ok_or("Game already ended")?;
+            self.act_for(&mut game, seat).await?;
+            seat
+        };
+
+        self.run_bots().await;
+        Ok(seat)
+    }
+
+    // 按机器人策略代 seat 行动，并通知同桌是超时代打
+    async fn act_for(&self, game: &mut GameState, seat: usize) -> Result<(), String> {
+        let cmd = bot_command(game, seat).ok_or("No move available")?;
+        self.broadcast(&Event::TurnTimedOut { player_id: seat })
+            .await;
+        self.play(game, cmd).await
+    }
+
+    async fn check_finished(&self) {
+        let mut phase_guard = self.phase.lock().await;
+        if !matches!(*phase_guard, ServerPhase::Playing) {
+            return;
+        }
+
+        let scores: Vec<i32> = {
+            let game = self.game.lock().await;
+            if game.phase != Phase::End {
+                return;
+            }
+            game.players.iter().map(|p| p.score).collect()
+        };
+        *phase_guard = ServerPhase::Finished;
+        METRICS.game_finished();
+        // 对局中掉线、没回来的玩家不再保留座位，人都走了房间由大厅清掉
+        {
+            let mut seating = self.seating.lock().await;
+            let gone: Vec<usize> = seating
+                .sessions
+                .values()
+                .copied()
+                .filter(|seat| !seating.conns.contains_key(seat))
+                .collect();
+            for seat in gone {
+                seating.remove(seat);
+            }
+        }
+
+        drop(phase_guard);
+
+        // 先取走日志，等数据库时重开的新一局不会被误删
+        let journal = self.journal.lock().unwrap().take();
+        if let Some(game_id) = self.game_id() {
+            match self.storage.finish_game(game_id, &scores).await {
+                Ok(ratings) if !ratings.is_empty() => {
+                    // 还在房间里的玩家随即看到新的分数
+                    {
+                        let mut seating = self.seating.lock().await;
+                        for account in seating.accounts.values_mut() {
+                            if let Some(rating) = ratings.get(&account.id) {
+                                account.rating = Some(*rating);
+                            }
+                        }
+                    }
+                    self.broadcast_room_state().await;
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    error!(room_id = self.id, game_id, error = %err, "failed to record game")
+                }
+            }
+        }
+        // 对局已结束，重启后无需恢复
+        if let Some(journal) = journal {
+            journal.remove();
+        }
+    }
+
+    async fn room_state(&self) -> NetMessage {
+        let seating = self.seating.lock().await;
+
+        let mut seated: Vec<usize> = seating.sessions.values().copied().collect();
+        seated.extend(seating.bots.iter().copied());
+        seated.sort();
+        let mut bots: Vec<usize> = seating.bots.iter().copied().collect();
+        bots.sort();
+        let mut ready: Vec<usize> = seating.ready.iter().copied().collect();
+        ready.sort();
+        let names = seated
+            .iter()
+            .filter_map(|seat| Some((*seat, seating.account_of(*seat)?.username.clone())))
+            .collect();
+        let ratings = self
+            .ratings(&seating)
+            .into_iter()
+            .map(|(seat, rating)| (seat, rating.round() as i32))
+            .collect();
+
+        NetMessage::RoomState {
+            seated,
+            bots,
+            ready,
+            host: seating.host,
+            names,
+            ratings,
+        }
+    }
+
+    async fn broadcast_room_state(&self) {
+        let state = self.room_state().await;
+        self.broadcast_message(&state).await;
+    }
+
+    pub async fn send_message(&self, player_id: usize, msg: &NetMessage) {
+        if let Some(outbox) = self.clients.lock().await.get(&player_id) {
+            outbox.send(msg);
+        }
+    }
+
+    pub async fn send_to_player(&self, player_id: usize, msg: &Event) {
+        self.send_message(player_id, &NetMessage::Event(msg.clone()))
+            .await;
+    }
+
+    pub async fn broadcast(&self, msg: &Event) {
+        self.broadcast_message(&NetMessage::Event(msg.clone()))
+            .await;
+    }
+
+    // 只是入队，不等待写出；持锁期间入队保证所有人收到的顺序一致
+    pub async fn broadcast_message(&self, msg: &NetMessage) {
+        let started = Instant::now();
+        let clients = self.clients.lock().await;
+        for outbox in clients.values() {
+            outbox.send(msg);
+        }
+        for outbox in self.spectators.lock().await.values() {
+            outbox.send(msg);
+        }
+        METRICS.broadcast_sent(started.elapsed());
+    }
+
+    /* ================= 发牌阶段 ================= */
+    async fn start_game(&self) {
+        // 登记本局：机器人座位不记令牌，登录玩家记下账号
+        let players: Vec<SeatRecord> = {
+            let seating = self.seating.lock().await;
+            (0..self.seats)
+                .map(|seat| SeatRecord {
+                    seat,
+                    token: (!seating.bots.contains(&seat))
+                        .then(|| seating.token_of(seat).cloned())
+                        .flatten(),
+                    account: seating.account_of(seat).cloned(),
+                })
+                .collect()
+        };
+
+        // 登记完成、拿到 game_id 之前一直持有对局锁，不会有指令漏记
+        let seed = rand::random();
+        let mut game = self.game.lock().await;
+        let events = game.deal_cards_seeded(seed);
+        {
+            let mut cast = self.cast.lock().await;
+            cast.clear();
+            cast.push(game.round, None, events.clone());
+            cast.release(&game);
+        }
+        *self.round_started.lock().await = Instant::now();
+        METRICS.game_started();
+
+        let record = NewGame {
+            room_id: self.id,
+            room_name: self.name.clone(),
+            invite_code: self.invite_code.clone(),
+            password: self.password.clone(),
+            rules: self.rules,
+            seed,
+            players: players.clone(),
+        };
+        match self.storage.start_game(record).await {
+            Ok(game_id) => {
+                *self.game_id.lock().unwrap() = Some(game_id);
+                self.record(None, &events);
+            }
+            Err(err) => error!(room_id = self.id, error = %err, "failed to record game"),
+        }
+
+        // 发牌之前先落日志
+        if let Some(config) = &self.storage.journal {
+            let entry = JournalEntry::Started {
+                game_id: self.game_id(),
+                room_id: self.id,
+                room_name: self.name.clone(),
+                invite_code: self.invite_code.clone(),
+                password: self.password.clone(),
+                seats: self.seats,
+                rules: self.rules,
+                seed,
+                players,
+                events: events.clone(),
+            };
+            match Journal::create(config, self.id, &entry) {
+                Ok(journal) => {
+                    *self.journal.lock().unwrap() = Some(journal);
+                }
+                Err(err) => error!(room_id = self.id, error = %err, "journal write failed"),
+            }
+        }
+        drop(game);
+
+        for event in events {
+            if let Event::CardsDealt { player_id, .. } = &event {
+                self.send_to_player(*player_id, &event).await;
+            }
+        }
+
+        self.broadcast(&Event::GameStarted).await;
+    }
+
+    /* ================= 管理：暂停 / 踢人 / 中止 ================= */
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    // 暂停或继续对局，仅游戏中可用
+    pub async fn set_paused(&self, paused: bool) -> Result<(), String> {
+        if !matches!(*self.phase.lock().await, ServerPhase::Playing) {
+            return Err("Game not in progress".to_string());
+        }
+        if self.paused.swap(paused, Ordering::Relaxed) == paused {
+            return Err(if paused {
+                "Game already paused".to_string()
+            } else {
+                "Game not paused".to_string()
+            });
+        }
+
+        if paused {
+            self.broadcast_message(&NetMessage::GamePaused).await;
+        } else {
+            self.broadcast_message(&NetMessage::GameResumed).await;
+            // 暂停期间轮到的机器人 / 掉线玩家
+            self.run_bots().await;
+        }
+        Ok(())
+    }
+
+    // 请出座位上的玩家并断开其连接；游戏中该座位改由机器人接管
+    pub async fn kick(&self, seat: usize, reason: &str) -> Result<(), String> {
+        let phase_guard = self.phase.lock().await;
+        {
+            let mut seating = self.seating.lock().await;
+            let Some(token) = seating.token_of(seat).cloned() else {
+                return Err("No player in that seat".to_string());
+            };
+
+            if matches!(*phase_guard, ServerPhase::Waiting) {
+                seating.remove(seat);
+            } else {
+                seating.sessions.remove(&token);
+                seating.accounts.remove(&token);
+                seating.conns.remove(&seat);
+                seating.dropped.remove(&seat);
+                seating.bots.insert(seat);
+                seating.hand_over_host(seat);
+            }
+        }
+        if let Some(outbox) = self.clients.lock().await.remove(&seat) {
+            outbox.send(&NetMessage::Error {
+                message: reason.to_string(),
+            });
+            outbox.finish();
+        }
+
+        if matches!(*phase_guard, ServerPhase::Playing) {
+            drop(phase_guard);
+            self.run_bots().await;
+        } else {
+            // 房主可能已换人
+            self.broadcast_room_state().await;
+        }
+        Ok(())
+    }
+
+    // 中止房间：记录为中止，通知所有人并断开连接；之后由大厅移除
+    pub async fn abort(&self, reason: &str) {
+        let mut phase_guard = self.phase.lock().await;
+        let was_playing = matches!(*phase_guard, ServerPhase::Playing);
+        *phase_guard = ServerPhase::Finished;
+        {
+            let mut seating = self.seating.lock().await;
+            seating.sessions.clear();
+            seating.accounts.clear();
+            seating.conns.clear();
+        }
+        let clients: Vec<Outbox> = self.clients.lock().await.drain().map(|(_, o)| o).collect();
+        drop(phase_guard);
+
+        for outbox in clients {
+            outbox.send(&NetMessage::RoomClosed {
+                reason: reason.to_string(),
+            });
+            outbox.finish();
+        }
+        self.close_spectators(reason).await;
+
+        if was_playing {
+            METRICS.game_aborted();
+            if let Some(game_id) = self.game_id() {
+                self.storage.abort_game(game_id);
+            }
+            let journal = self.journal.lock().unwrap().take();
+            if let Some(journal) = journal {
+                journal.remove();
+            }
+        }
+    }
+
+    /* ================= 桌上投票：暂停 / 继续 / 中止 / 重开 ================= */
+
+    // 计票交给 GameState；机器人和掉线玩家随大流投赞成票，通过后按议题执行
+    async fn vote(
+        self: &Arc<Self>,
+        player_id: usize,
+        motion: Motion,
+        yes: bool,
+    ) -> Result<(), String> {
+        let phase = *self.phase.lock().await;
+        match (phase, motion) {
+            (ServerPhase::Finished, Motion::Restart) | (ServerPhase::Playing, _) => {}
+            (_, Motion::Restart) => return Err("Game not finished".to_string()),
+            _ => return Err("Game not in progress".to_string()),
+        }
+        match motion {
+            Motion::Pause if self.is_paused() => return Err("Game already paused".to_string()),
+            Motion::Resume if !self.is_paused() => return Err("Game not paused".to_string()),
+            _ => {}
+        }
+
+        let mut unattended = self.unattended().await;
+        if motion == Motion::Restart {
+            // 已经离开的玩家不拦着别人重开
+            let seating = self.seating.lock().await;
+            unattended.extend((0..self.seats).filter(|seat| !seating.is_taken(*seat)));
+        }
+        let (opened, passed) = {
+            let mut game = self.game.lock().await;
+            let mut events = game.apply(Command::Vote {
+                player_id,
+                motion,
+                yes,
+            })?;
+            let started = events
+                .iter()
+                .any(|e| matches!(e, Event::VoteStarted { .. }));
+            if started {
+                for seat in unattended {
+                    if game.vote.is_none() {
+                        break;
+                    }
+                    // 发起人恰好掉线时已经投过
+                    if let Ok(cast) = game.apply(Command::Vote {
+                        player_id: seat,
+                        motion,
+                        yes: true,
+                    }) {
+                        events.extend(cast);
+                    }
+                }
+            }
+            self.dispatch(&events).await;
+
+            // 新发起且还没有结果的投票需要计时
+            let opened = game.vote.as_ref().filter(|_| started).map(|vote| vote.id);
+            let passed = events
+                .iter()
+                .any(|e| matches!(e, Event::VoteEnded { passed: true, .. }));
+            (opened, passed)
+        };
+
+        if let Some(id) = opened {
+            let room = Arc::downgrade(self);
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_secs(VOTE_SECS)).await;
+                if let Some(room) = room.upgrade() {
+                    room.expire_vote(id).await;
+                }
+            });
+        }
+        if passed {
+            info!(room_id = self.id, ?motion, "vote passed");
+            match motion {
+                // 暂停状态在计票后可能已被管理员改变
+                Motion::Pause | Motion::Resume => {
+                    let _ = self.set_paused(motion == Motion::Pause).await;
+                }
+                Motion::Abort => self.abort("Aborted by vote").await,
+                Motion::Restart => self.reset_game().await,
+            }
+        }
+        Ok(())
+    }
+
+    async fn expire_vote(&self, id: u32) {
+        let mut game = self.game.lock().await;
+        if let Some(event) = game.expire_vote(id) {
+            self.dispatch(&[event]).await;
+        }
+    }
+
+    /* ================= 停机 ================= */
+
+    // 冻结房间并断开所有连接，会话令牌保留给重启后重连
+    // 进行中的对局返回快照；等人阶段的房间不保留
+    pub async fn shut_down(&self) -> Option<RoomSnapshot> {
+        let was_paused = self.paused.swap(true, Ordering::Relaxed);
+        // 取得游戏锁后不会再有指令执行
+        let snapshot = self.snapshot().await.map(|snapshot| RoomSnapshot {
+            paused: was_paused,
+            ..snapshot
+        });
+
+        for (_, outbox) in self.clients.lock().await.drain() {
+            outbox.finish();
+        }
+        for (_, outbox) in self.spectators.lock().await.drain() {
+            outbox.finish();
+        }
+        for outbox in self.cast.lock().await.drain_viewers() {
+            outbox.finish();
+        }
+        snapshot
+    }
+
+    // 进行中对局的当前状态，房间照常运行
+    pub async fn snapshot(&self) -> Option<RoomSnapshot> {
+        if !matches!(*self.phase.lock().await, ServerPhase::Playing) {
+            return None;
+        }
+
+        let (sessions, accounts, bots, host) = {
+            let seating = self.seating.lock().await;
+            let mut bots: Vec<usize> = seating.bots.iter().copied().collect();
+            bots.sort();
+            (
+                seating.sessions.clone(),
+                seating.accounts.clone(),
+                bots,
+                seating.host,
+            )
+        };
+        let (state, cast) = {
+            let game = self.game.lock().await;
+            (game.clone(), self.cast.lock().await.steps())
+        };
+
+        Some(RoomSnapshot {
+            room_id: self.id,
+            room_name: self.name.clone(),
+            invite_code: self.invite_code.clone(),
+            password: self.password.clone(),
+            seats: self.seats,
+            rules: self.rules,
+            game_id: self.game_id(),
+            sessions,
+            accounts,
+            bots,
+            host,
+            paused: self.is_paused(),
+            state,
+            score_history: self.score_history.lock().await.clone(),
+            cast,
+        })
+    }
+
+    /* ================= 重开投票阶段 ================= */
+    // 重开投票通过：回到等人阶段，座位保留，人类玩家重新准备后由房主开局
+    pub async fn reset_game(&self) {
+        let mut phase_guard = self.phase.lock().await;
+        if !matches!(*phase_guard, ServerPhase::Finished) {
+            return;
+        }
+        *self.game.lock().await = init_game(self.seats, self.rules);
+        *phase_guard = ServerPhase::Waiting;
+        {
+            let mut seating = self.seating.lock().await;
+            let bots = seating.bots.clone();
+            seating.ready.retain(|seat| bots.contains(seat));
+        }
+        *self.game_id.lock().unwrap() = None;
+        self.score_history.lock().await.clear();
+        drop(phase_guard);
+        info!(room_id = self.id, "game reset");
+
+        self.broadcast(&Event::PhaseChanged).await;
+        self.broadcast_room_state().await;
+    }
+}
+
+// 行动计时：每秒检查一次当前行动的玩家，按阶段时限倒计时，超时代为行动
+async fn turn_timer(room: Weak<Room>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    let mut turn = None; // (轮数, 阶段, 行动玩家)
+    let mut seconds_left = 0;
+
+    loop {
+        interval.tick().await;
+        let Some(room) = room.upgrade() else {
+            return;
+        };
+        if !matches!(*room.phase.lock().await, ServerPhase::Playing) {
+            return;
+        }
+        // 暂停时倒计时停住，继续后接着计
+        if room.is_paused() {
+            continue;
+        }
+
+        let (current, limit) = {
+            let game = room.game.lock().await;
+            let Some(actor) = game.actor() else {
+                return;
+            };
+            (
+                (game.round, game.phase, actor),
+                room.rules.timers.limit(game.phase),
+            )
+        };
+
+        // 轮到的玩家掉线超过宽限期，交给机器人
+        if room.bot_controlled().await.contains(&current.2) {
+            turn = None;
+            room.run_bots().await;
+            continue;
+        }
+
+        // 不限时的阶段
+        if limit == 0 {
+            turn = None;
+            continue;
+        }
+
+        if turn != Some(current) {
+            turn = Some(current);
+            seconds_left = limit;
+        } else {
+            seconds_left -= 1;
+        }
+
+        if seconds_left == 0 {
+            turn = None;
+            room.time_out(current).await;
+            continue;
+        }
+
+        // 开始、剩 10 秒和最后 5 秒时广播倒计时
+        if seconds_left == limit || seconds_left == 10 || seconds_left <= 5 {
+            room.broadcast(&Event::TurnTimer {
+                player_id: current.2,
+                seconds_left,
+            })
+            .await;
+        }
+    }
+}
+
+fn new_session_token() -> String {
+    rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(16)
+        .map(char::from)
+        .collect()
+}
+
+/* ===== 初始化 GameState ===== */
+
+fn init_game(seats: usize, rules: GameRules) -> GameState {
+    let players = (0..seats)
+        .map(|id| PlayerState {
+            id,
+            is_first: false,
+            hand: vec![],
+            score: 0,
+            prediction: None,
+            posterior_prediction: None,
+            has_predicted: false,
+            has_played: false,
+        })
+        .collect();
+
+    GameState::with_rules(players, rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rating::RatingConfig;
+    use crate::transport;
+    use tokio::net::{TcpListener, TcpStream};
+
+    // 连到本机一个监听端口，只为拿到一个能写的发送队列
+    async fn outbox() -> Outbox {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (_, writer) = transport::tcp(stream, 4096);
+        Outbox::spawn(writer)
+    }
+
+    // 前 humans 个座位是真人（连接编号从 1 起）、其余是机器人的三人房间，已开局，轮到 0 号预测
+    async fn started_room(name: &str, humans: usize) -> (Arc<Room>, Vec<String>) {
+        let path = std::env::temp_dir().join(format!("{}-{}.db", name, std::process::id()));
+        for ext in ["db", "db-wal", "db-shm"] {
+            let _ = std::fs::remove_file(path.with_extension(ext));
+        }
+        let storage = Storage::open(&path, None, RatingConfig::default()).unwrap();
+        let room = Arc::new(Room::new(
+            1,
+            name.to_string(),
+            3,
+            GameRules::default(),
+            None,
+            None,
+            Arc::new(storage),
+        ));
+
+        let mut tokens = vec![];
+        for conn_id in 1..=humans as u64 {
+            let (player_id, token) = room.join(outbox().await, conn_id, None).await.unwrap();
+            room.set_ready(player_id, true).await.unwrap();
+            tokens.push(token);
+        }
+        room.add_bots(0).await.unwrap();
+        room.start(0).await.unwrap();
+        assert_eq!(room.game.lock().await.actor(), Some(0));
+        (room, tokens)
+    }
+
+    #[tokio::test]
+    async fn reconnect_within_grace_keeps_the_turn() {
+        let (room, tokens) = started_room("room-grace", 1).await;
+        let token = &tokens[0];
+        let hand = room.game.lock().await.players[0].hand.clone();
+
+        assert_eq!(room.disconnect(token, 1).await, Some(0));
+        room.run_bots().await;
+        assert_eq!(room.game.lock().await.actor(), Some(0));
+
+        assert_eq!(room.reconnect(token, outbox().await, 2).await, Some(0));
+        let game = room.game.lock().await;
+        assert_eq!(game.actor(), Some(0));
+        assert_eq!(game.players[0].hand, hand);
+        assert_eq!(game.players[0].prediction, None);
+    }
+
+    #[tokio::test]
+    async fn bots_take_over_after_grace() {
+        let (room, tokens) = started_room("room-takeover", 1).await;
+
+        room.disconnect(&tokens[0], 1).await;
+        room.seating.lock().await.dropped.insert(
+            0,
+            Instant::now() - Duration::from_secs(RECONNECT_GRACE_SECS),
+        );
+        room.run_bots().await;
+        assert_ne!(room.game.lock().await.actor(), Some(0));
+    }
+
+    #[tokio::test]
+    async fn kicked_host_hands_over_during_the_game() {
+        let (room, _) = started_room("room-kick", 2).await;
+
+        room.kick(0, "Kicked").await.unwrap();
+        let seating = room.seating.lock().await;
+        assert!(seating.bots.contains(&0));
+        assert_eq!(seating.host, Some(1));
+        drop(seating);
+
+        room.kick(1, "Kicked").await.unwrap();
+        assert_eq!(room.seating.lock().await.host, None);
+    }
+}
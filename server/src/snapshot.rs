@@ -0,0 +1,75 @@
+use crate::accounts::Account;
+use clap::ValueEnum;
+use game_core::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+// 启动时发现停机快照如何处理
+pub enum RestorePolicy {
+    Ask,    // 在终端上询问，非交互运行时恢复
+    Always, // 直接恢复
+    Never,  // 丢弃快照，其中的对局记为中止
+}
+
+// 停机时一个进行中房间的完整状态，重启后直接还原，不必重放日志
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RoomSnapshot {
+    pub room_id: u32,
+    pub room_name: String,
+    pub invite_code: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    pub seats: usize,
+    pub rules: GameRules,
+    pub game_id: Option<i64>,
+    pub sessions: HashMap<String, usize>, // 会话令牌 -> 座位，玩家凭令牌重连
+    #[serde(default)]
+    pub accounts: HashMap<String, Account>, // 会话令牌 -> 登录的账号
+    pub bots: Vec<usize>,
+    pub host: Option<usize>,
+    pub paused: bool, // 停机前是否已被暂停
+    pub state: GameState,
+    pub score_history: Vec<Vec<i32>>,
+    #[serde(default)]
+    pub cast: Vec<NetMessage>, // 解说视角的事件流，均为 CastStep
+}
+
+// 先写临时文件再改名，停机中途被杀也不会留下半个快照
+pub fn save(path: &Path, rooms: &[RoomSnapshot]) -> Result<(), String> {
+    let tmp = path.with_extension("json.tmp");
+    let text = serde_json::to_string(rooms).unwrap();
+    fs::write(&tmp, text)
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(|e| format!("Cannot write snapshot {}: {}", path.display(), e))
+}
+
+// 没有快照时返回空列表
+pub fn load(path: &Path) -> Result<Vec<RoomSnapshot>, String> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read snapshot {}: {}", path.display(), e))?;
+    serde_json::from_str(&text).map_err(|e| format!("Corrupt snapshot {}: {}", path.display(), e))
+}
+
+pub fn remove(path: &Path) -> Result<(), String> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(format!("Cannot remove snapshot {}: {}", path.display(), e)),
+    }
+}
+
+// 读不了的快照改名保留，免得每次启动都报错
+pub fn discard(path: &Path) {
+    let failed = path.with_extension("json.failed");
+    if let Err(err) = fs::rename(path, &failed) {
+        warn!(path = %path.display(), error = %err, "cannot rename snapshot");
+    }
+}
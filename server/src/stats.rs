@@ -0,0 +1,146 @@
+use game_core::*;
+
+// 一局结束时每个座位的成绩，存进 game_players，排行榜由此汇总
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SeatResult {
+    pub score: i32,
+    pub placement: usize,         // 按最终得分的名次，同分并列
+    pub prior_predictions: u32,   // 做了先验预测的轮数
+    pub prior_correct: u32,       // 其中猜中的轮数
+    pub posterior_positions: u32, // 后验预测给出的名次个数
+    pub posterior_correct: u32,   // 其中猜中的个数
+}
+
+pub const LEADERBOARD_DEFAULT: usize = 10;
+pub const LEADERBOARD_MAX: usize = 100;
+
+// 对局日志里的一条，按写入顺序排列
+pub enum Logged {
+    Command(Command),
+    Event(Event),
+}
+
+// 按对局日志逐轮统计：RoundResult 里有每个人的先验预测和本轮排名，
+// 后验预测只有一人能做，是谁要看同一轮里的 PosteriorPredict 指令
+pub fn tally(scores: &[i32], log: &[Logged]) -> Vec<SeatResult> {
+    let mut results: Vec<SeatResult> = scores
+        .iter()
+        .map(|&score| SeatResult {
+            score,
+            // 比自己分高的人数 + 1
+            placement: scores.iter().filter(|other| **other > score).count() + 1,
+            ..SeatResult::default()
+        })
+        .collect();
+
+    let mut predictor = None;
+    for entry in log {
+        let Logged::Event(Event::RoundResult {
+            ranking,
+            prediction,
+            posterior_prediction,
+            ..
+        }) = entry
+        else {
+            if let Logged::Command(Command::PosteriorPredict {
+                player_id,
+                rank_list: Some(_),
+            }) = entry
+            {
+                predictor = Some(*player_id);
+            }
+            continue;
+        };
+
+        // 先验预测为 0 表示这一轮没有预测
+        for (player_id, &rank) in prediction.iter().enumerate() {
+            let Some(result) = results.get_mut(player_id) else {
+                continue;
+            };
+            if rank == 0 {
+                continue;
+            }
+            result.prior_predictions += 1;
+            if ranking.get(rank - 1) == Some(&player_id) {
+                result.prior_correct += 1;
+            }
+        }
+
+        if let Some(result) = predictor.take().and_then(|id| results.get_mut(id))
+            && !posterior_prediction.is_empty()
+        {
+            result.posterior_positions += posterior_prediction.len() as u32;
+            result.posterior_correct += posterior_prediction
+                .iter()
+                .zip(ranking)
+                .filter(|(predicted, actual)| predicted == actual)
+                .count() as u32;
+        }
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round(ranking: Vec<usize>, prediction: Vec<usize>, posterior: Vec<usize>) -> Logged {
+        Logged::Event(Event::RoundResult {
+            cards: vec![],
+            score_delta: vec![0; ranking.len()],
+            ranking,
+            prediction,
+            posterior_prediction: posterior,
+        })
+    }
+
+    fn posterior(player_id: usize, rank_list: Option<Vec<usize>>) -> Logged {
+        Logged::Command(Command::PosteriorPredict {
+            player_id,
+            rank_list,
+        })
+    }
+
+    #[test]
+    fn tied_scores_share_a_placement() {
+        let results = tally(&[3, 5, 3, -1], &[]);
+        let placements: Vec<usize> = results.iter().map(|r| r.placement).collect();
+        assert_eq!(placements, vec![2, 1, 2, 4]);
+    }
+
+    #[test]
+    fn rounds_without_a_prediction_are_not_counted() {
+        let log = [
+            // 0 号猜中第一，1 号猜错，2 号没有预测
+            posterior(0, None),
+            round(vec![0, 2, 1], vec![1, 1, 0], vec![]),
+            // 谁都没有预测
+            posterior(1, None),
+            round(vec![1, 0, 2], vec![0, 0, 0], vec![]),
+        ];
+        let results = tally(&[0, 0, 0], &log);
+        assert_eq!(
+            (results[0].prior_predictions, results[0].prior_correct),
+            (1, 1)
+        );
+        assert_eq!(
+            (results[1].prior_predictions, results[1].prior_correct),
+            (1, 0)
+        );
+        assert_eq!(results[2].prior_predictions, 0);
+        assert!(results.iter().all(|r| r.posterior_positions == 0));
+    }
+
+    #[test]
+    fn posterior_longer_than_the_table() {
+        // 多出的名次算作已给出，但不可能猜中
+        let log = [
+            posterior(1, Some(vec![2, 0, 1, 2, 0])),
+            round(vec![2, 0, 1], vec![0, 0, 0], vec![2, 0, 1, 2, 0]),
+        ];
+        let results = tally(&[0, 0, 0], &log);
+        assert_eq!(results[1].posterior_positions, 5);
+        assert_eq!(results[1].posterior_correct, 3);
+        assert_eq!(results[0].posterior_positions, 0);
+    }
+}
@@ -0,0 +1,683 @@
+use crate::accounts::Account;
+use crate::journal::JournalConfig;
+use crate::rating::RatingConfig;
+use crate::stats::{self, Logged};
+use game_core::*;
+use rusqlite::{Connection, ErrorCode, OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Mutex, mpsc};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::oneshot;
+use tracing::error;
+
+// 对局记录：每局的玩家、发牌种子、全部指令与事件、最终得分
+// 对局记录的写入交给写线程按顺序执行，房间不在异步线程上等数据库；出错只影响记录，不影响对局
+pub struct Storage {
+    conn: Mutex<Connection>,            // 恢复对局、账号和排行榜用
+    writer: mpsc::Sender<Write>,        // 对局记录交给写线程
+    pub journal: Option<JournalConfig>, // 预写日志的设置，None 表示不写日志
+    pub rating: RatingConfig,
+}
+
+// 一个座位在对局开始时的情况
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeatRecord {
+    pub seat: usize,
+    pub token: Option<String>, // 人类玩家的会话令牌，机器人为 None
+    #[serde(default)]
+    pub account: Option<Account>, // 登录玩家的账号，游客和机器人为 None
+}
+
+// 尚未结束的对局，用于重启后恢复
+pub struct SavedGame {
+    pub game_id: Option<i64>, // 数据库写入失败时只有日志，没有编号
+    pub room_id: u32,
+    pub room_name: String,
+    pub invite_code: Option<String>,
+    pub password: Option<String>, // 入座密码，恢复后照旧校验
+    pub seats: usize,
+    pub rules: GameRules,
+    pub players: Vec<SeatRecord>,
+    pub dealt: Vec<Event>,      // 开局时的发牌事件
+    pub commands: Vec<Command>, // 按执行顺序
+}
+
+// 开局时登记的一局
+pub struct NewGame {
+    pub room_id: u32,
+    pub room_name: String,
+    pub invite_code: Option<String>,
+    pub password: Option<String>,
+    pub rules: GameRules,
+    pub seed: u64,
+    pub players: Vec<SeatRecord>,
+}
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS games (
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    room_id      INTEGER NOT NULL,
+    room_name    TEXT NOT NULL,
+    invite_code  TEXT,
+    password     TEXT,
+    seats        INTEGER NOT NULL,
+    rules        TEXT NOT NULL,
+    seed         INTEGER NOT NULL,
+    status       TEXT NOT NULL,
+    started_at   INTEGER NOT NULL,
+    finished_at  INTEGER,
+    final_scores TEXT
+);
+CREATE TABLE IF NOT EXISTS accounts (
+    id            INTEGER PRIMARY KEY AUTOINCREMENT,
+    username      TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    password_hash TEXT NOT NULL,
+    created_at    INTEGER NOT NULL,
+    rating        REAL,                       -- NULL 表示还没打过计分的对局，按初始分算
+    rated_games   INTEGER NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS game_players (
+    game_id    INTEGER NOT NULL REFERENCES games(id),
+    seat       INTEGER NOT NULL,
+    bot        INTEGER NOT NULL,
+    token      TEXT,
+    account_id INTEGER REFERENCES accounts(id),
+    -- 以下在对局正常结束时填写
+    score                 INTEGER,
+    placement             INTEGER,
+    prior_predictions     INTEGER,
+    prior_correct         INTEGER,
+    posterior_positions   INTEGER,
+    posterior_correct     INTEGER,
+    rating_change         REAL,               -- 只有登录玩家有
+    PRIMARY KEY (game_id, seat)
+);
+CREATE TABLE IF NOT EXISTS game_log (
+    id      INTEGER PRIMARY KEY AUTOINCREMENT,
+    game_id INTEGER NOT NULL REFERENCES games(id),
+    kind    TEXT NOT NULL,
+    payload TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS game_log_game ON game_log(game_id, id);
+";
+
+impl Storage {
+    pub fn open(
+        path: &Path,
+        journal: Option<JournalConfig>,
+        rating: RatingConfig,
+    ) -> Result<Self, String> {
+        let open = || -> rusqlite::Result<Connection> {
+            let conn = Connection::open(path)?;
+            conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA foreign_keys = ON;")?;
+            // 写线程和读连接各用一个连接，偶尔争用时等一会儿
+            conn.busy_timeout(Duration::from_secs(5))?;
+            Ok(conn)
+        };
+        let conn = open()
+            .and_then(|conn| conn.execute_batch(SCHEMA).map(|_| conn))
+            .map_err(|e| format!("Cannot initialize database {}: {}", path.display(), e))?;
+        let writer = Writer {
+            conn: open().map_err(|e| format!("Cannot open database {}: {}", path.display(), e))?,
+            rating: rating.clone(),
+        };
+
+        let (sender, jobs) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("storage".to_string())
+            .spawn(move || writer.run(jobs))
+            .map_err(|e| format!("Cannot start storage writer: {}", e))?;
+
+        Ok(Storage {
+            conn: Mutex::new(conn),
+            writer: sender,
+            journal,
+            rating,
+        })
+    }
+
+    // 开局时登记一局，返回 game_id
+    pub async fn start_game(&self, game: NewGame) -> Result<i64, String> {
+        let (reply, result) = oneshot::channel();
+        self.write(Write::Start { game, reply });
+        result.await.map_err(|_| WRITER_STOPPED.to_string())?
+    }
+
+    // 记录一条指令（系统发牌等没有指令）及其产生的事件；不等写完
+    pub fn record(&self, game_id: i64, cmd: Option<&Command>, events: &[Event]) {
+        self.write(Write::Record {
+            game_id,
+            cmd: cmd.cloned(),
+            events: events.to_vec(),
+        });
+    }
+
+    // 记下最终得分，并按对局日志算出每个座位的成绩，供排行榜汇总；登录玩家同时更新分数
+    // 返回账号编号 -> 新的分数，本局不计分时为空
+    pub async fn finish_game(
+        &self,
+        game_id: i64,
+        scores: &[i32],
+    ) -> Result<HashMap<i64, f64>, String> {
+        let (reply, result) = oneshot::channel();
+        self.write(Write::Finish {
+            game_id,
+            scores: scores.to_vec(),
+            reply,
+        });
+        result.await.map_err(|_| WRITER_STOPPED.to_string())?
+    }
+
+    // 无法恢复的对局标记为中止，不再尝试；不等写完
+    pub fn abort_game(&self, game_id: i64) {
+        self.write(Write::Abort { game_id });
+    }
+
+    // 等此前交给写线程的写入全部完成，停机前调用
+    pub async fn flush(&self) {
+        let (reply, done) = oneshot::channel();
+        self.write(Write::Flush(reply));
+        let _ = done.await;
+    }
+
+    fn write(&self, job: Write) {
+        if self.writer.send(job).is_err() {
+            error!("{}", WRITER_STOPPED);
+        }
+    }
+
+    // 所有进行中的对局及其指令
+    pub fn unfinished_games(&self) -> Result<Vec<SavedGame>, String> {
+        let conn = self.conn.lock().unwrap();
+
+        let games = conn
+            .prepare(
+                "SELECT id, room_id, room_name, invite_code, password, seats, rules
+                 FROM games WHERE status = 'playing' ORDER BY id",
+            )
+            .map_err(db_error)?
+            .query_map([], |row| {
+                let game_id: i64 = row.get(0)?;
+                let rules: String = row.get(6)?;
+                Ok((
+                    game_id,
+                    SavedGame {
+                        game_id: Some(game_id),
+                        room_id: row.get(1)?,
+                        room_name: row.get(2)?,
+                        invite_code: row.get(3)?,
+                        password: row.get(4)?,
+                        seats: row.get::<_, i64>(5)? as usize,
+                        rules: GameRules::default(),
+                        players: vec![],
+                        dealt: vec![],
+                        commands: vec![],
+                    },
+                    rules,
+                ))
+            })
+            .map_err(db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(db_error)?;
+
+        let mut saved = vec![];
+        for (game_id, mut game, rules) in games {
+            game.rules = serde_json::from_str(&rules)
+                .map_err(|e| format!("Corrupt rules in game {}: {}", game_id, e))?;
+
+            game.players = conn
+                .prepare(
+                    "SELECT p.seat, p.token, a.id, a.username, a.rating
+                     FROM game_players p LEFT JOIN accounts a ON a.id = p.account_id
+                     WHERE p.game_id = ?1 ORDER BY p.seat",
+                )
+                .map_err(db_error)?
+                .query_map([game_id], |row| {
+                    let account_id: Option<i64> = row.get(2)?;
+                    let username: Option<String> = row.get(3)?;
+                    let rating: Option<f64> = row.get(4)?;
+                    Ok(SeatRecord {
+                        seat: row.get::<_, i64>(0)? as usize,
+                        token: row.get(1)?,
+                        account: account_id.zip(username).map(|(id, username)| Account {
+                            id,
+                            username,
+                            rating,
+                        }),
+                    })
+                })
+                .map_err(db_error)?
+                .collect::<Result<_, _>>()
+                .map_err(db_error)?;
+
+            for entry in read_log(&conn, game_id)? {
+                match entry {
+                    Logged::Command(cmd) => game.commands.push(cmd),
+                    Logged::Event(event @ Event::CardsDealt { .. }) => game.dealt.push(event),
+                    Logged::Event(_) => {}
+                }
+            }
+
+            saved.push(game);
+        }
+        Ok(saved)
+    }
+}
+
+/* ================= 写线程 ================= */
+
+const WRITER_STOPPED: &str = "Storage writer stopped";
+
+// 交给写线程的一项写入，按提交顺序执行；需要结果的带上回复通道
+enum Write {
+    Start {
+        game: NewGame,
+        reply: oneshot::Sender<Result<i64, String>>,
+    },
+    Record {
+        game_id: i64,
+        cmd: Option<Command>,
+        events: Vec<Event>,
+    },
+    Finish {
+        game_id: i64,
+        scores: Vec<i32>,
+        reply: oneshot::Sender<Result<HashMap<i64, f64>, String>>,
+    },
+    Abort {
+        game_id: i64,
+    },
+    Flush(oneshot::Sender<()>),
+}
+
+// 独占一个连接，在自己的线程上执行所有对局记录的写入，不占用异步工作线程
+struct Writer {
+    conn: Connection,
+    rating: RatingConfig,
+}
+
+impl Writer {
+    fn run(mut self, jobs: mpsc::Receiver<Write>) {
+        for job in jobs {
+            match job {
+                Write::Start { game, reply } => {
+                    let _ = reply.send(self.start_game(&game));
+                }
+                Write::Record {
+                    game_id,
+                    cmd,
+                    events,
+                } => {
+                    if let Err(err) = self.record(game_id, cmd.as_ref(), &events) {
+                        error!(game_id, error = %err, "failed to record game");
+                    }
+                }
+                Write::Finish {
+                    game_id,
+                    scores,
+                    reply,
+                } => {
+                    let _ = reply.send(self.finish_game(game_id, &scores));
+                }
+                Write::Abort { game_id } => {
+                    if let Err(err) = self.abort_game(game_id) {
+                        error!(game_id, error = %err, "failed to record game");
+                    }
+                }
+                Write::Flush(reply) => {
+                    let _ = reply.send(());
+                }
+            }
+        }
+    }
+
+    // 开局时登记一局，返回 game_id
+    fn start_game(&mut self, game: &NewGame) -> Result<i64, String> {
+        let tx = self.conn.transaction().map_err(db_error)?;
+
+        tx.execute(
+            "INSERT INTO games (room_id, room_name, invite_code, password, seats, rules, seed, status, started_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 'playing', ?8)",
+            params![
+                game.room_id,
+                game.room_name,
+                game.invite_code,
+                game.password,
+                game.players.len() as i64,
+                serde_json::to_string(&game.rules).unwrap(),
+                // SQLite 只有有符号整数，按位存取
+                game.seed as i64,
+                now(),
+            ],
+        )
+        .map_err(db_error)?;
+        let game_id = tx.last_insert_rowid();
+
+        for player in &game.players {
+            tx.execute(
+                "INSERT INTO game_players (game_id, seat, bot, token, account_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    game_id,
+                    player.seat as i64,
+                    player.token.is_none(),
+                    player.token,
+                    player.account.as_ref().map(|account| account.id)
+                ],
+            )
+            .map_err(db_error)?;
+        }
+
+        tx.commit().map_err(db_error)?;
+        Ok(game_id)
+    }
+
+    // 记录一条指令（系统发牌等没有指令）及其产生的事件
+    fn record(
+        &mut self,
+        game_id: i64,
+        cmd: Option<&Command>,
+        events: &[Event],
+    ) -> Result<(), String> {
+        let tx = self.conn.transaction().map_err(db_error)?;
+
+        {
+            let mut insert = tx
+                .prepare_cached("INSERT INTO game_log (game_id, kind, payload) VALUES (?1, ?2, ?3)")
+                .map_err(db_error)?;
+            if let Some(cmd) = cmd {
+                insert
+                    .execute(params![
+                        game_id,
+                        "command",
+                        serde_json::to_string(cmd).unwrap()
+                    ])
+                    .map_err(db_error)?;
+            }
+            for event in events {
+                insert
+                    .execute(params![
+                        game_id,
+                        "event",
+                        serde_json::to_string(event).unwrap()
+                    ])
+                    .map_err(db_error)?;
+            }
+        }
+
+        tx.commit().map_err(db_error)
+    }
+
+    // 记下最终得分，并按对局日志算出每个座位的成绩，供排行榜汇总；登录玩家同时更新分数
+    fn finish_game(&mut self, game_id: i64, scores: &[i32]) -> Result<HashMap<i64, f64>, String> {
+        let tx = self.conn.transaction().map_err(db_error)?;
+
+        tx.execute(
+            "UPDATE games SET status = 'finished', finished_at = ?2, final_scores = ?3 WHERE id = ?1",
+            params![game_id, now(), serde_json::to_string(scores).unwrap()],
+        )
+        .map_err(db_error)?;
+
+        let log = read_log(&tx, game_id)?;
+        let results = stats::tally(scores, &log);
+        {
+            let mut update = tx
+                .prepare_cached(
+                    "UPDATE game_players SET score = ?3, placement = ?4,
+                         prior_predictions = ?5, prior_correct = ?6,
+                         posterior_positions = ?7, posterior_correct = ?8
+                     WHERE game_id = ?1 AND seat = ?2",
+                )
+                .map_err(db_error)?;
+            for (seat, result) in results.iter().enumerate() {
+                update
+                    .execute(params![
+                        game_id,
+                        seat as i64,
+                        result.score,
+                        result.placement as i64,
+                        result.prior_predictions,
+                        result.prior_correct,
+                        result.posterior_positions,
+                        result.posterior_correct
+                    ])
+                    .map_err(db_error)?;
+            }
+        }
+        let ratings = update_ratings(&tx, &self.rating, game_id, &results)?;
+
+        tx.commit().map_err(db_error)?;
+        Ok(ratings)
+    }
+
+    fn abort_game(&self, game_id: i64) -> Result<(), String> {
+        self.conn
+            .execute(
+                "UPDATE games SET status = 'aborted', finished_at = ?2 WHERE id = ?1 AND status = 'playing'",
+                params![game_id, now()],
+            )
+            .map_err(db_error)?;
+        Ok(())
+    }
+}
+
+fn update_ratings(
+    conn: &Connection,
+    config: &RatingConfig,
+    game_id: i64,
+    results: &[stats::SeatResult],
+) -> Result<HashMap<i64, f64>, String> {
+    // 机器人不参与计分，避免对着机器人刷分
+    let seats: Vec<(usize, Option<i64>, Option<f64>)> = conn
+        .prepare_cached(
+            "SELECT p.seat, a.id, a.rating
+             FROM game_players p LEFT JOIN accounts a ON a.id = p.account_id
+             WHERE p.game_id = ?1 AND p.bot = 0 ORDER BY p.seat",
+        )
+        .map_err(db_error)?
+        .query_map([game_id], |row| {
+            Ok((row.get::<_, i64>(0)? as usize, row.get(1)?, row.get(2)?))
+        })
+        .map_err(db_error)?
+        .collect::<Result<_, _>>()
+        .map_err(db_error)?;
+    // 少于两个账号的对局不计分
+    let accounts = seats
+        .iter()
+        .filter(|(_, account, _)| account.is_some())
+        .count();
+    if accounts < 2 || seats.iter().any(|(seat, _, _)| *seat >= results.len()) {
+        return Ok(HashMap::new());
+    }
+
+    let ratings: Vec<f64> = seats
+        .iter()
+        .map(|(_, _, rating)| rating.unwrap_or(config.initial))
+        .collect();
+    let placements: Vec<usize> = seats
+        .iter()
+        .map(|(seat, _, _)| results[*seat].placement)
+        .collect();
+    let changes = config.changes(&ratings, &placements);
+
+    let mut updated = HashMap::new();
+    for ((seat, account, _), (rating, change)) in seats.iter().zip(ratings.iter().zip(changes)) {
+        let Some(account) = account else {
+            continue;
+        };
+        conn.execute(
+            "UPDATE accounts SET rating = ?2, rated_games = rated_games + 1 WHERE id = ?1",
+            params![account, rating + change],
+        )
+        .map_err(db_error)?;
+        conn.execute(
+            "UPDATE game_players SET rating_change = ?3 WHERE game_id = ?1 AND seat = ?2",
+            params![game_id, *seat as i64, change],
+        )
+        .map_err(db_error)?;
+        updated.insert(*account, rating + change);
+    }
+    Ok(updated)
+}
+
+/* ================= 账号 ================= */
+
+impl Storage {
+    // 用户名已被占用（不区分大小写）时报错
+    pub fn create_account(&self, username: &str, password_hash: &str) -> Result<i64, String> {
+        let conn = self.conn.lock().unwrap();
+        match conn.execute(
+            "INSERT INTO accounts (username, password_hash, created_at) VALUES (?1, ?2, ?3)",
+            params![username, password_hash, now()],
+        ) {
+            Ok(_) => Ok(conn.last_insert_rowid()),
+            Err(rusqlite::Error::SqliteFailure(e, _))
+                if e.code == ErrorCode::ConstraintViolation =>
+            {
+                Err("Username already taken".to_string())
+            }
+            Err(e) => Err(db_error(e)),
+        }
+    }
+
+    // 账号当前的分数，还没打过计分对局时为 None
+    pub fn rating(&self, account_id: i64) -> Result<Option<f64>, String> {
+        self.conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT rating FROM accounts WHERE id = ?1",
+                [account_id],
+                |row| row.get(0),
+            )
+            .map_err(db_error)
+    }
+
+    // 返回账号和密码哈希
+    pub fn find_account(&self, username: &str) -> Result<Option<(Account, String)>, String> {
+        self.conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT id, username, rating, password_hash FROM accounts WHERE username = ?1",
+                [username],
+                |row| {
+                    Ok((
+                        Account {
+                            id: row.get(0)?,
+                            username: row.get(1)?,
+                            rating: row.get(2)?,
+                        },
+                        row.get(3)?,
+                    ))
+                },
+            )
+            .optional()
+            .map_err(db_error)
+    }
+}
+
+/* ================= 排行榜 ================= */
+
+impl Storage {
+    // 只统计登录玩家正常结束的对局；按分数、胜场、局数排序
+    pub fn leaderboard(&self, limit: usize) -> Result<Vec<PlayerStats>, String> {
+        self.conn
+            .lock()
+            .unwrap()
+            .prepare(&format!(
+                "SELECT {STATS_COLUMNS} {STATS_FROM}
+                 GROUP BY a.id
+                 ORDER BY rating DESC, wins DESC, games DESC, a.username
+                 LIMIT ?1"
+            ))
+            .map_err(db_error)?
+            .query_map(params![limit as i64, self.rating.initial], player_stats)
+            .map_err(db_error)?
+            .collect::<Result<_, _>>()
+            .map_err(db_error)
+    }
+
+    // 没有打完过对局的账号返回 None
+    pub fn player_stats(&self, username: &str) -> Result<Option<PlayerStats>, String> {
+        self.conn
+            .lock()
+            .unwrap()
+            .query_row(
+                &format!("SELECT {STATS_COLUMNS} {STATS_FROM} AND a.username = ?1 GROUP BY a.id"),
+                params![username, self.rating.initial],
+                player_stats,
+            )
+            .optional()
+            .map_err(db_error)
+    }
+}
+
+const STATS_COLUMNS: &str = "
+    a.username,
+    COUNT(*) AS games,
+    SUM(p.placement = 1) AS wins,
+    AVG(p.score),
+    SUM(p.prior_predictions), SUM(p.prior_correct),
+    SUM(p.posterior_positions), SUM(p.posterior_correct),
+    AVG(p.placement) AS average_placement,
+    COALESCE(a.rating, ?2) AS rating,
+    a.rated_games";
+
+const STATS_FROM: &str = "
+    FROM game_players p
+    JOIN accounts a ON a.id = p.account_id
+    JOIN games g ON g.id = p.game_id
+    WHERE g.status = 'finished' AND p.placement IS NOT NULL";
+
+fn player_stats(row: &rusqlite::Row) -> rusqlite::Result<PlayerStats> {
+    // 从没做过预测时准确率为 None
+    let accuracy = |made: i64, correct: i64| (made > 0).then(|| correct as f64 / made as f64);
+    Ok(PlayerStats {
+        username: row.get(0)?,
+        games: row.get::<_, i64>(1)? as u32,
+        wins: row.get::<_, i64>(2)? as u32,
+        average_score: row.get(3)?,
+        prior_accuracy: accuracy(row.get(4)?, row.get(5)?),
+        posterior_accuracy: accuracy(row.get(6)?, row.get(7)?),
+        average_placement: row.get(8)?,
+        rating: row.get(9)?,
+        rated_games: row.get::<_, i64>(10)? as u32,
+    })
+}
+
+// 一局的日志，指令和事件按写入顺序排列
+fn read_log(conn: &Connection, game_id: i64) -> Result<Vec<Logged>, String> {
+    let rows: Vec<(String, String)> = conn
+        .prepare_cached("SELECT kind, payload FROM game_log WHERE game_id = ?1 ORDER BY id")
+        .map_err(db_error)?
+        .query_map([game_id], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(db_error)?
+        .collect::<Result<_, _>>()
+        .map_err(db_error)?;
+
+    let corrupt = |e: serde_json::Error| format!("Corrupt log in game {}: {}", game_id, e);
+    rows.into_iter()
+        .map(|(kind, payload)| match kind.as_str() {
+            "command" => serde_json::from_str(&payload)
+                .map(Logged::Command)
+                .map_err(corrupt),
+            _ => serde_json::from_str(&payload)
+                .map(Logged::Event)
+                .map_err(corrupt),
+        })
+        .collect()
+}
+
+fn db_error(e: rusqlite::Error) -> String {
+    format!("Database error: {}", e)
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
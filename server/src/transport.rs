@@ -0,0 +1,156 @@
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tracing::debug;
+
+// 两种传输承载同一套协议：TCP 上每行一条 JSON，WebSocket 上每个文本帧一条 JSON
+pub enum LineReader {
+    Tcp(TcpLines),
+    WebSocket(SplitStream<WebSocketStream<TcpStream>>),
+}
+
+// 按行读取并限制长度；读到一半的行留在这里，被 select! 取消也不会丢
+pub struct TcpLines {
+    reader: BufReader<OwnedReadHalf>,
+    line: Vec<u8>,
+    too_long: bool, // 当前行已超长，丢弃到行尾为止
+    max_bytes: usize,
+}
+
+// 无法当作消息处理的输入
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BadLine {
+    TooLong,
+    NotUtf8,
+}
+
+pub enum LineWriter {
+    Tcp(OwnedWriteHalf),
+    WebSocket(SplitSink<WebSocketStream<TcpStream>, Message>),
+}
+
+pub fn tcp(stream: TcpStream, max_bytes: usize) -> (LineReader, LineWriter) {
+    let (r, w) = stream.into_split();
+    let lines = TcpLines {
+        reader: BufReader::new(r),
+        line: Vec::new(),
+        too_long: false,
+        max_bytes,
+    };
+    (LineReader::Tcp(lines), LineWriter::Tcp(w))
+}
+
+// WebSocket 握手，失败时直接丢弃连接；超长的消息由底层拒绝并断开
+pub async fn websocket(
+    stream: TcpStream,
+    max_bytes: usize,
+) -> Result<(LineReader, LineWriter), String> {
+    let config = WebSocketConfig::default()
+        .max_message_size(Some(max_bytes))
+        .max_frame_size(Some(max_bytes));
+    let ws = tokio_tungstenite::accept_async_with_config(stream, Some(config))
+        .await
+        .map_err(|e| format!("WebSocket handshake failed: {}", e))?;
+    let (w, r) = ws.split();
+
+    Ok((LineReader::WebSocket(r), LineWriter::WebSocket(w)))
+}
+
+impl LineReader {
+    // 下一条消息；连接断开时返回 None。可以在 select! 中取消，不会丢消息
+    pub async fn next_line(&mut self) -> Option<Result<String, BadLine>> {
+        match self {
+            LineReader::Tcp(lines) => lines.next_line().await,
+            LineReader::WebSocket(stream) => loop {
+                match stream.next().await? {
+                    Ok(Message::Text(text)) => return Some(Ok(text.to_string())),
+                    Ok(Message::Binary(data)) => {
+                        return Some(
+                            String::from_utf8(data.to_vec()).map_err(|_| BadLine::NotUtf8),
+                        );
+                    }
+                    // Ping 由底层自动回应
+                    Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_)) => continue,
+                    Ok(Message::Close(_)) | Err(_) => return None,
+                }
+            },
+        }
+    }
+}
+
+impl TcpLines {
+    async fn next_line(&mut self) -> Option<Result<String, BadLine>> {
+        loop {
+            let buf = self.reader.fill_buf().await.ok()?;
+            if buf.is_empty() {
+                return None; // 断开时丢弃不完整的最后一行
+            }
+
+            let (chunk, end) = match buf.iter().position(|b| *b == b'\n') {
+                Some(i) => (&buf[..i], Some(i + 1)),
+                None => (buf, None),
+            };
+            if !self.too_long {
+                self.line.extend_from_slice(chunk);
+                if self.line.len() > self.max_bytes {
+                    self.too_long = true;
+                    self.line = Vec::new();
+                }
+            }
+            let used = end.unwrap_or(buf.len());
+            self.reader.consume(used);
+
+            if end.is_some() {
+                let line = std::mem::take(&mut self.line);
+                if std::mem::take(&mut self.too_long) {
+                    return Some(Err(BadLine::TooLong));
+                }
+                let mut text = String::from_utf8(line).map_err(|_| BadLine::NotUtf8);
+                if let Ok(text) = &mut text
+                    && text.ends_with('\r')
+                {
+                    text.pop();
+                }
+                return Some(text);
+            }
+        }
+    }
+}
+
+impl LineWriter {
+    pub async fn write_line(&mut self, line: &str) -> Result<(), String> {
+        match self {
+            LineWriter::Tcp(writer) => {
+                let text = format!("{}\n", line);
+                writer
+                    .write_all(text.as_bytes())
+                    .await
+                    .map_err(|e| e.to_string())
+            }
+            LineWriter::WebSocket(sink) => sink
+                .send(Message::text(line))
+                .await
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    pub async fn shutdown(&mut self) {
+        match self {
+            LineWriter::Tcp(writer) => {
+                if let Err(err) = writer.shutdown().await {
+                    debug!(error = %err, "shutdown failed");
+                }
+            }
+            LineWriter::WebSocket(sink) => {
+                if let Err(err) = sink.close().await {
+                    debug!(error = %err, "close failed");
+                }
+            }
+        }
+    }
+}